@@ -6,9 +6,67 @@ const {
   simpleSign,
   simpleVerify,
   invokeUcan,
+  invokeUcanWithDisclosures,
   decodeUcan,
-  verifyUcan
+  canonicalizeUcan,
+  verifyUcanSignature,
+  verifyUcan,
+  checkUcan,
+  verifyBundle,
+  pruneProofChain,
+  computeAttenuation,
+  buildCapabilities,
+  describeSemantics,
+  validateCapabilities,
+  buildProofChain: buildProofChainHandle,
+  proofChainReduce,
+  proofChainFacts,
+  proofChainFactProvenance,
+  proofChainCids,
+  proofChainJson,
+  markUsed,
+  wasUsed,
+  createStoreNamespace,
+  dropStoreNamespace,
+  selfTest,
+  onTokenExpiring: onTokenExpiringId,
+  offTokenExpiring,
+  configureRuntime,
+  shutdown,
+  setTime
 } = createRequire(import.meta.url)("./index.node")
+
+class ProofChain {
+  #handle
+  constructor(handle) {
+    this.#handle = handle
+  }
+  reduce(requiredCapabilities, options) {
+    return proofChainReduce(this.#handle, { ...options, requiredCapabilities })
+  }
+  facts() {
+    return proofChainFacts(this.#handle)
+  }
+  factProvenance() {
+    return proofChainFactProvenance(this.#handle)
+  }
+  cids() {
+    return proofChainCids(this.#handle)
+  }
+  chainJson() {
+    return proofChainJson(this.#handle)
+  }
+}
+
+async function buildProofChain(token, options) {
+  return new ProofChain(await buildProofChainHandle(token, options ?? {}))
+}
+
+function onTokenExpiring(callback, options) {
+  const id = onTokenExpiringId(callback, options)
+  return () => offTokenExpiring(id)
+}
+
 export {
   createDid,
   resolveDid,
@@ -16,7 +74,27 @@ export {
   simpleSign,
   simpleVerify,
   invokeUcan,
+  invokeUcanWithDisclosures,
   decodeUcan,
-  verifyUcan
+  canonicalizeUcan,
+  verifyUcanSignature,
+  verifyUcan,
+  checkUcan,
+  verifyBundle,
+  pruneProofChain,
+  computeAttenuation,
+  buildCapabilities,
+  describeSemantics,
+  validateCapabilities,
+  buildProofChain,
+  markUsed,
+  wasUsed,
+  createStoreNamespace,
+  dropStoreNamespace,
+  selfTest,
+  onTokenExpiring,
+  configureRuntime,
+  shutdown,
+  setTime
 }
 export default createRequire(import.meta.url)("./index.node")
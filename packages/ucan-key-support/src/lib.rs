@@ -7,3 +7,6 @@ pub mod web_crypto;
 pub mod ed25519;
 pub mod p256;
 pub mod rsa;
+
+/// The version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
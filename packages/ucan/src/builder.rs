@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 use crate::{
     capability::{proof::ProofDelegationSemantics, Capability, CapabilitySemantics},
     crypto::KeyMaterial,
+    disclosure::{digest_disclosure, make_disclosure, DIGESTS_FACT},
     serde::Base64Encode,
     time::now,
     ucan::{FactsMap, Ucan, UcanHeader, UcanPayload, UCAN_VERSION},
@@ -14,6 +15,10 @@ use multihash_codetable::Code;
 use rand::Rng;
 use serde::{de::DeserializeOwned, Serialize};
 
+fn random_nonce() -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
 /// A signable is a UCAN that has all the state it needs in order to be signed,
 /// but has not yet been signed.
 /// NOTE: This may be useful for bespoke signing flows down the road. It is
@@ -34,6 +39,19 @@ where
     pub facts: FactsMap,
     pub proofs: Vec<String>,
     pub add_nonce: bool,
+    /// The nonce to include instead of a random one. Built with
+    /// [UcanBuilder::with_nonce], it is the nonce generated for the UCAN, so
+    /// that it is known before signing.
+    pub nonce: Option<String>,
+
+    pub typ: String,
+    pub header_fields: FactsMap,
+    /// Unregistered claims of the payload.
+    pub extra_claims: FactsMap,
+    pub canonical_json: bool,
+
+    /// Disclosures of hidden facts, by name.
+    pub disclosures: BTreeMap<String, String>,
 }
 
 impl<'a, K> Signable<'a, K>
@@ -44,18 +62,17 @@ where
     pub fn ucan_header(&self) -> UcanHeader {
         UcanHeader {
             alg: self.issuer.get_jwt_algorithm_name(),
-            typ: "JWT".into(),
+            typ: self.typ.clone(),
+            extra: self.header_fields.clone(),
         }
     }
 
     /// The payload field components of the UCAN JWT
     pub async fn ucan_payload(&self) -> Result<UcanPayload> {
-        let nonce = match self.add_nonce {
-            true => Some(
-                base64::engine::general_purpose::URL_SAFE_NO_PAD
-                    .encode(rand::thread_rng().gen::<[u8; 32]>()),
-            ),
-            false => None,
+        let nonce = match (&self.nonce, self.add_nonce) {
+            (Some(nonce), _) => Some(nonce.clone()),
+            (None, true) => Some(random_nonce()),
+            (None, false) => None,
         };
 
         let facts = if self.facts.is_empty() {
@@ -80,6 +97,7 @@ where
             cap: self.capabilities.clone().try_into()?,
             fct: facts,
             prf: proofs,
+            extra: self.extra_claims.clone(),
         })
     }
 
@@ -92,8 +110,14 @@ where
             .await
             .expect("Unable to generate UCAN payload");
 
-        let header_base64 = header.jwt_base64_encode()?;
-        let payload_base64 = payload.jwt_base64_encode()?;
+        let (header_base64, payload_base64) = if self.canonical_json {
+            (
+                header.jwt_base64_encode_canonical()?,
+                payload.jwt_base64_encode_canonical()?,
+            )
+        } else {
+            (header.jwt_base64_encode()?, payload.jwt_base64_encode()?)
+        };
 
         let data_to_sign = format!("{header_base64}.{payload_base64}")
             .as_bytes()
@@ -122,8 +146,14 @@ where
     facts: FactsMap,
     proofs: Vec<String>,
     add_nonce: bool,
+    nonce: Option<String>,
 
     add_proof_facts: bool,
+    typ: String,
+    header_fields: FactsMap,
+    extra_claims: FactsMap,
+    canonical_json: bool,
+    disclosures: BTreeMap<String, String>,
 }
 
 impl<'a, K> Default for UcanBuilder<'a, K>
@@ -152,8 +182,14 @@ where
             facts: BTreeMap::new(),
             proofs: Vec::new(),
             add_nonce: false,
+            nonce: None,
 
             add_proof_facts: false,
+            typ: "JWT".into(),
+            header_fields: BTreeMap::new(),
+            extra_claims: BTreeMap::new(),
+            canonical_json: false,
+            disclosures: BTreeMap::new(),
         }
     }
 }
@@ -225,18 +261,85 @@ where
         self
     }
 
+    /// Add a hidden fact: only the digest of its disclosure is added to the
+    /// "_sd" fact. The disclosure is returned by the built [Signable].
+    pub fn with_hidden_fact<T: Serialize>(mut self, key: &str, fact: T) -> Result<Self> {
+        let disclosure = make_disclosure(key, &serde_json::to_value(fact)?)?;
+        let digests = self
+            .facts
+            .entry(DIGESTS_FACT.to_owned())
+            .or_insert_with(|| serde_json::json!({}));
+        match digests.as_object_mut() {
+            Some(digests) => {
+                digests.insert(
+                    key.to_owned(),
+                    serde_json::Value::String(digest_disclosure(&disclosure)),
+                );
+            }
+            None => return Err(anyhow!(r#"Fact "{}" must be an object"#, DIGESTS_FACT)),
+        }
+        self.disclosures.insert(key.to_owned(), disclosure);
+        Ok(self)
+    }
+
     /// Will ensure that the built UCAN includes a number used once.
     pub fn with_nonce(mut self) -> Self {
         self.add_nonce = true;
         self
     }
 
+    /// Will ensure that the built UCAN includes the given number used once,
+    /// like the nonce of a UCAN being re-issued.
+    pub fn with_nonce_value(mut self, nonce: &str) -> Self {
+        self.nonce = Some(nonce.to_owned());
+        self
+    }
+
     /// Will add a collection of proof tokens (if any) to the facts field "prf".
     pub fn with_add_proof_facts(mut self, add_proof_facts: bool) -> Self {
         self.add_proof_facts = add_proof_facts;
         self
     }
 
+    /// Set the "typ" field of the JWT header (default is "JWT").
+    pub fn with_type(mut self, typ: &str) -> Self {
+        self.typ = String::from(typ);
+        self
+    }
+
+    /// Add a parameter (like "kid") to the JWT header. "alg" is set by the
+    /// issuer key, and "typ" by [Self::with_type].
+    pub fn with_header_field<T: Serialize>(mut self, key: &str, value: T) -> Result<Self> {
+        if key == "alg" || key == "typ" {
+            return Err(anyhow!(r#"Header field "{}" can't be set"#, key));
+        }
+        self.header_fields
+            .insert(key.to_owned(), serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    /// Add an unregistered claim to the payload, like one preserved when
+    /// decoding a UCAN. The claims of the UCAN spec are set by the other
+    /// methods.
+    pub fn with_extra_claim<T: Serialize>(mut self, key: &str, value: T) -> Result<Self> {
+        const CLAIMS: [&str; 9] = [
+            "ucv", "iss", "aud", "exp", "nbf", "nnc", "cap", "fct", "prf",
+        ];
+        if CLAIMS.contains(&key) {
+            return Err(anyhow!(r#"Claim "{}" can't be set"#, key));
+        }
+        self.extra_claims
+            .insert(key.to_owned(), serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    /// Encode the header and payload as canonical JSON (RFC 8785), so equal
+    /// UCANs always produce equal tokens.
+    pub fn with_canonical_json(mut self, canonical_json: bool) -> Self {
+        self.canonical_json = canonical_json;
+        self
+    }
+
     /// Includes a UCAN in the list of proofs for the UCAN to be built.
     /// Note that the proof's audience must match this UCAN's issuer
     /// or else the proof chain will be invalidated!
@@ -253,6 +356,10 @@ where
     }
 
     fn insert_proof(&mut self, proof: &cid::Cid, authority: &Ucan) -> Result<()> {
+        if self.proofs.contains(&proof.to_string()) {
+            // already witnessed, e.g. both as a proof and a delegation
+            return Ok(());
+        }
         self.proofs.push(proof.to_string());
         if self.add_proof_facts {
             if !self.facts.contains_key("prf") {
@@ -356,6 +463,15 @@ where
                     capabilities: self.capabilities.clone(),
                     proofs: self.proofs.clone(),
                     add_nonce: self.add_nonce,
+                    nonce: self
+                        .nonce
+                        .clone()
+                        .or_else(|| self.add_nonce.then(random_nonce)),
+                    typ: self.typ.clone(),
+                    header_fields: self.header_fields.clone(),
+                    extra_claims: self.extra_claims.clone(),
+                    canonical_json: self.canonical_json,
+                    disclosures: self.disclosures.clone(),
                 }),
                 None => Err(anyhow!("Missing audience")),
             },
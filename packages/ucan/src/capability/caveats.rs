@@ -3,6 +3,12 @@ use std::ops::Deref;
 use anyhow::{anyhow, Error, Result};
 use serde_json::{Map, Value};
 
+/// The caveat limiting how many more times a capability may be re-delegated,
+/// like `{ "maxDepth": 2 }`: each proof link claiming the capability uses one,
+/// except the invocation. It isn't a condition of the capability, so
+/// [Caveat::enables] ignores it; invalid values mean no re-delegation.
+pub const MAX_DEPTH_CAVEAT: &str = "maxDepth";
+
 #[derive(Clone)]
 pub struct Caveat(Map<String, Value>);
 
@@ -27,21 +33,15 @@ impl Caveat {
     /// assert!(!x_caveat.enables(&x_diff_caveat));
     /// assert!(!x_caveat.enables(&no_caveat));
     /// assert!(!x_caveat.enables(&y_caveat));
+    ///
+    /// let depth_caveat = Caveat::try_from(json!({ "maxDepth": 1 })).unwrap();
+    /// assert!(depth_caveat.enables(&no_caveat));
     /// ```
     pub fn enables(&self, other: &Caveat) -> bool {
-        if self.is_empty() {
-            return true;
-        }
-
-        if other.is_empty() {
-            return false;
-        }
-
-        if self == other {
-            return true;
-        }
-
         for (key, value) in self.iter() {
+            if key == MAX_DEPTH_CAVEAT {
+                continue;
+            }
             if let Some(other_value) = other.get(key) {
                 if value != other_value {
                     return false;
@@ -53,6 +53,13 @@ impl Caveat {
 
         true
     }
+
+    /// Returns how many more times the capability may be re-delegated (see
+    /// [MAX_DEPTH_CAVEAT]), None if unlimited.
+    pub fn max_depth(&self) -> Option<u64> {
+        self.get(MAX_DEPTH_CAVEAT)
+            .map(|depth| depth.as_u64().unwrap_or(0))
+    }
 }
 
 impl Deref for Caveat {
@@ -7,7 +7,7 @@ use serde::{
 use serde_json::Value;
 use std::{
     collections::{btree_map::Iter as BTreeMapIter, BTreeMap},
-    fmt::Debug,
+    fmt::{Debug, Display},
     iter::FlatMap,
     ops::Deref,
 };
@@ -214,44 +214,127 @@ impl TryFrom<CapabilitiesImpl> for Capabilities {
     }
 }
 
-impl TryFrom<&Value> for Capabilities {
-    type Error = anyhow::Error;
+/// A malformed part of capabilities, located by resource, ability and index
+/// of the caveat.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapabilityError {
+    pub resource: Option<String>,
+    pub ability: Option<String>,
+    pub caveat: Option<usize>,
+    pub message: String,
+}
 
-    fn try_from(value: &Value) -> Result<Self, Self::Error> {
-        let map = value
-            .as_object()
-            .ok_or_else(|| anyhow!("Capabilities must be an object."))?;
-        let mut resources: CapabilitiesImpl = BTreeMap::new();
+impl CapabilityError {
+    fn new(
+        resource: Option<&str>,
+        ability: Option<&str>,
+        caveat: Option<usize>,
+        message: String,
+    ) -> Self {
+        CapabilityError {
+            resource: resource.map(str::to_owned),
+            ability: ability.map(str::to_owned),
+            caveat,
+            message,
+        }
+    }
+}
 
-        for (key, value) in map.iter() {
-            let resource = key.to_owned();
-            let abilities_object = value
-                .as_object()
-                .ok_or_else(|| anyhow!("Abilities must be an object."))?;
+impl Display for CapabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(resource) = &self.resource {
+            write!(f, " (resource {:?}", resource)?;
+            if let Some(ability) = &self.ability {
+                write!(f, ", ability {:?}", ability)?;
+            }
+            if let Some(caveat) = &self.caveat {
+                write!(f, ", caveat #{}", caveat)?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
 
-            let abilities = {
-                let mut abilities: AbilitiesImpl = BTreeMap::new();
-                for (key, value) in abilities_object.iter() {
-                    let ability = key.to_owned();
-                    let mut caveats: Vec<Value> = vec![];
+impl Capabilities {
+    /// Returns all the errors of the `{resource: {ability: [caveat]}}` shape,
+    /// which [Capabilities::try_from] rejects.
+    pub fn validate(value: &Value) -> Vec<CapabilityError> {
+        let mut errors = Vec::new();
+        let map = match value.as_object() {
+            Some(map) => map,
+            None => {
+                errors.push(CapabilityError::new(
+                    None,
+                    None,
+                    None,
+                    "Capabilities must be an object.".to_owned(),
+                ));
+                return errors;
+            }
+        };
 
-                    let array = value
-                        .as_array()
-                        .ok_or_else(|| anyhow!("Caveats must be defined as an array."))?;
-                    for value in array.iter() {
-                        if !value.is_object() {
-                            return Err(anyhow!("Caveat must be an object: {}", value));
-                        }
-                        caveats.push(value.to_owned());
-                    }
-                    abilities.insert(ability, caveats);
+        for (resource, abilities) in map.iter() {
+            let abilities = match abilities.as_object() {
+                Some(abilities) => abilities,
+                None => {
+                    errors.push(CapabilityError::new(
+                        Some(resource),
+                        None,
+                        None,
+                        "Abilities must be an object.".to_owned(),
+                    ));
+                    continue;
                 }
-                abilities
             };
-
-            resources.insert(resource, abilities);
+            if abilities.is_empty() {
+                // [0.10.0/3.2.6.2](https://github.com/ucan-wg/spec#3262-abilities):
+                // One or more abilities MUST be given for each resource.
+                errors.push(CapabilityError::new(
+                    Some(resource),
+                    None,
+                    None,
+                    format!("No abilities given for resource: {}", resource),
+                ));
+            }
+            for (ability, caveats) in abilities.iter() {
+                let caveats = match caveats.as_array() {
+                    Some(caveats) => caveats,
+                    None => {
+                        errors.push(CapabilityError::new(
+                            Some(resource),
+                            Some(ability),
+                            None,
+                            "Caveats must be defined as an array.".to_owned(),
+                        ));
+                        continue;
+                    }
+                };
+                for (index, caveat) in caveats.iter().enumerate() {
+                    if !caveat.is_object() {
+                        errors.push(CapabilityError::new(
+                            Some(resource),
+                            Some(ability),
+                            Some(index),
+                            format!("Caveat must be an object: {}", caveat),
+                        ));
+                    }
+                }
+            }
         }
+        errors
+    }
+}
 
-        Capabilities::try_from(resources)
+impl TryFrom<&Value> for Capabilities {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        if let Some(error) = Capabilities::validate(value).into_iter().next() {
+            return Err(anyhow!(error.to_string()));
+        }
+        let t: CapabilitiesImpl = serde_json::from_value(value.clone())?;
+        Ok(Capabilities(t))
     }
 }
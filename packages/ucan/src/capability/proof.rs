@@ -9,7 +9,11 @@ pub enum ProofAction {
     Delegate,
 }
 
-impl Ability for ProofAction {}
+impl Ability for ProofAction {
+    fn enables(&self, other: &Self) -> bool {
+        self >= other
+    }
+}
 
 impl TryFrom<String> for ProofAction {
     type Error = anyhow::Error;
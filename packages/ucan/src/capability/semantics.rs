@@ -9,7 +9,10 @@ pub trait Scope: ToString + TryFrom<Url> + PartialEq + Clone {
     fn contains(&self, other: &Self) -> bool;
 }
 
-pub trait Ability: Ord + TryFrom<String> + ToString + Clone {}
+pub trait Ability: TryFrom<String> + ToString + Clone {
+    /// Returns true if this ability enables (includes) the other one.
+    fn enables(&self, other: &Self) -> bool;
+}
 
 #[derive(Clone, Eq, PartialEq)]
 pub enum Resource<S>
@@ -75,6 +78,11 @@ where
         }
     }
 
+    /// Parse a resource string, either a proof selection ("ucan:...") or an URI.
+    fn parse_resource(&self, resource: &str) -> Option<Resource<S>> {
+        parse_resource(self, resource)
+    }
+
     /// Parse a resource and abilities string and a caveats object.
     /// The default "no caveats" (`[{}]`) is implied if `None` caveats given.
     fn parse(
@@ -83,13 +91,7 @@ where
         ability: &str,
         caveat: Option<&Value>,
     ) -> Option<CapabilityView<S, A>> {
-        // "ucan://did..." cannot be parsed by "url" crate
-        let cap_resource = if resource.starts_with("ucan:") {
-            Resource::Ucan(ProofSelection::try_from(resource.to_owned()).ok()?)
-        } else {
-            let uri = Url::parse(resource).ok()?;
-            Resource::ResourceUri(self.parse_scope(&uri)?)
-        };
+        let cap_resource = self.parse_resource(resource)?;
 
         let cap_ability = match self.parse_action(ability) {
             Some(ability) => ability,
@@ -108,6 +110,36 @@ where
     fn parse_capability(&self, value: &Capability) -> Option<CapabilityView<S, A>> {
         self.parse(&value.resource, &value.ability, Some(&value.caveat))
     }
+
+    /// Parse a capability claimed by the given issuer, for semantics where
+    /// the meaning of a capability depends on its issuer (like ownership).
+    fn parse_issued_capability(
+        &self,
+        value: &Capability,
+        _issuer: &str,
+    ) -> Option<CapabilityView<S, A>> {
+        self.parse_capability(value)
+    }
+}
+
+/// Parses a resource string, either a proof selection ("ucan:...") or an URI
+/// parsed with [CapabilitySemantics::parse_scope]: the default
+/// [CapabilitySemantics::parse_resource], for implementations overriding it.
+pub fn parse_resource<S, A, T>(semantics: &T, resource: &str) -> Option<Resource<S>>
+where
+    S: Scope,
+    A: Ability,
+    T: CapabilitySemantics<S, A> + ?Sized,
+{
+    // "ucan://did..." cannot be parsed by "url" crate
+    if resource.starts_with("ucan:") {
+        Some(Resource::Ucan(
+            ProofSelection::try_from(resource.to_owned()).ok()?,
+        ))
+    } else {
+        let uri = Url::parse(resource).ok()?;
+        Some(Resource::ResourceUri(semantics.parse_scope(&uri)?))
+    }
 }
 
 #[derive(Clone, Eq, PartialEq)]
@@ -168,7 +200,7 @@ where
         ) {
             (Ok(self_caveat), Ok(other_caveat)) => {
                 self.resource.contains(&other.resource)
-                    && self.ability >= other.ability
+                    && self.ability.enables(&other.ability)
                     && self_caveat.enables(&other_caveat)
             }
             _ => false,
@@ -1,25 +1,49 @@
 use crate::{
     capability::{
         proof::{ProofDelegationSemantics, ProofSelection},
-        Ability, CapabilitySemantics, CapabilityView, Resource, Scope,
+        Ability, CapabilitySemantics, CapabilityView, Caveat, Resource, Scope,
     },
-    crypto::did::DidParser,
+    crypto::did::{normalize_did, DidParser},
     store::UcanJwtStore,
     ucan::Ucan,
 };
 use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
 use cid::Cid;
+use libipld_core::ipld::Ipld;
 use multihash_codetable::Code;
 use std::{collections::BTreeSet, fmt::Debug};
 
 const PROOF_DELEGATION_SEMANTICS: ProofDelegationSemantics = ProofDelegationSemantics {};
 
+/// Returns the re-delegation depth allowed by the capability caveat.
+fn max_depth<S: Scope, A: Ability>(capability: &CapabilityView<S, A>) -> Option<u64> {
+    Caveat::try_from(capability.caveat())
+        .ok()
+        .and_then(|caveat| caveat.max_depth())
+}
+
+/// The larger of remaining depths, None being unlimited.
+fn max_remaining_depth(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    a.zip(b).map(|(a, b)| a.max(b))
+}
+
+/// The smaller of remaining depths, None being unlimited.
+fn min_remaining_depth(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 #[derive(Eq, PartialEq)]
 pub struct CapabilityInfo<S: Scope, A: Ability> {
     pub originators: BTreeSet<String>,
     pub not_before: Option<u64>,
     pub expires_at: Option<u64>,
+    /// How many more times the capability may be re-delegated (see
+    /// [crate::capability::MAX_DEPTH_CAVEAT]), None if unlimited
+    pub remaining_depth: Option<u64>,
     pub capability: CapabilityView<S, A>,
 }
 
@@ -33,28 +57,79 @@ where
             .field("originators", &self.originators)
             .field("not_before", &self.not_before)
             .field("expires_at", &self.expires_at)
+            .field("remaining_depth", &self.remaining_depth)
             .field("capability", &self.capability)
             .finish()
     }
 }
 
+/// Where the UCAN of a [ProofChain] was found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofSource {
+    /// The UCAN the chain is instantiated from
+    Token,
+    /// A proof embedded into the "prf" fact of the UCAN it proves
+    Facts,
+    /// A proof read from the store
+    Store,
+}
+
+/// What to do with a capability which the semantics can't parse while
+/// reducing capabilities of a [ProofChain]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseFailureMode {
+    /// Drop the capability and all the following capabilities of the UCAN
+    #[default]
+    Truncate,
+    /// Drop only the capability, reporting it as a warning
+    Skip,
+    /// Fail the reduction
+    Fail,
+}
+
+/// How [ProofChain::from_ucan_with] resolves the proofs of a UCAN
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResolveOptions {
+    /// Decode the proofs with [Ucan::from_str_lenient]
+    pub lenient: bool,
+    /// Leave out the proofs which the store doesn't have, instead of failing,
+    /// like a bundle of only the proofs needed: they can't enable anything
+    pub skip_missing: bool,
+}
+
 /// A deserialized chain of ancestral proofs that are linked to a UCAN
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ProofChain {
     ucan: Ucan,
     proofs: Vec<ProofChain>,
     redelegations: BTreeSet<Cid>,
+    source: ProofSource,
 }
 
 impl ProofChain {
     /// Instantiate a [ProofChain] from a [Ucan], given a [UcanJwtStore] and [DidParser]
+    pub async fn from_ucan<S>(
+        ucan: Ucan,
+        now_time: Option<u64>,
+        did_parser: &mut DidParser,
+        store: &S,
+    ) -> Result<ProofChain>
+    where
+        S: UcanJwtStore,
+    {
+        Self::from_ucan_with(ucan, now_time, did_parser, store, ResolveOptions::default()).await
+    }
+
+    /// Instantiate a [ProofChain] like [ProofChain::from_ucan], resolving the
+    /// proofs as the options tell
     #[cfg_attr(target_arch = "wasm32", async_recursion(?Send))]
     #[cfg_attr(not(target_arch = "wasm32"), async_recursion)]
-    pub async fn from_ucan<S>(
+    pub async fn from_ucan_with<S>(
         ucan: Ucan,
         now_time: Option<u64>,
         did_parser: &mut DidParser,
         store: &S,
+        options: ResolveOptions,
     ) -> Result<ProofChain>
     where
         S: UcanJwtStore,
@@ -62,22 +137,91 @@ impl ProofChain {
         ucan.validate(now_time, did_parser).await?;
 
         let mut proofs: Vec<ProofChain> = Vec::new();
+        let mut missing: Vec<Cid> = Vec::new();
 
         if let Some(ucan_proofs) = ucan.proofs() {
             for cid_string in ucan_proofs.iter() {
                 let cid = Cid::try_from(cid_string.as_str())?;
                 // Try to get embedded proof, then request a storage
-                let ucan_token = match ucan.require_token(&cid) {
-                    Some(token) => token,
-                    None => store.require_token(&cid).await?,
+                let (ucan_token, source) = match ucan.require_token(&cid) {
+                    Some(token) => (token, ProofSource::Facts),
+                    None if options.skip_missing && store.read::<Ipld>(&cid).await?.is_none() => {
+                        missing.push(cid);
+                        continue;
+                    }
+                    None => (store.require_token(&cid).await?, ProofSource::Store),
                 };
-                let proof_chain =
-                    Self::try_from_token_string(&ucan_token, now_time, did_parser, store).await?;
+                let proof_ucan = match options.lenient {
+                    true => Ucan::from_str_lenient(&ucan_token)?,
+                    false => Ucan::try_from(ucan_token.as_str())?,
+                };
+                // the token must be the one the CID refers to
+                let hasher = Code::try_from(cid.hash().code())
+                    .map_err(|_| anyhow!("Unsupported hasher of proof CID {}", cid))?;
+                if proof_ucan.to_cid(hasher)? != cid {
+                    return Err(anyhow!("Proof token doesn't match CID {}", cid));
+                }
+                let mut proof_chain =
+                    Self::from_ucan_with(proof_ucan, now_time, did_parser, store, options).await?;
                 proof_chain.validate_link_to(&ucan)?;
+                proof_chain.source = source;
                 proofs.push(proof_chain);
             }
         }
 
+        let redelegations = Self::redelegations(&ucan, &proofs, &missing)?;
+
+        Ok(ProofChain {
+            ucan,
+            proofs,
+            redelegations,
+            source: ProofSource::Token,
+        })
+    }
+
+    /// Instantiate a [ProofChain] from a [Ucan] and the chains of its proofs,
+    /// already resolved. The signature of the UCAN isn't validated: the UCAN
+    /// may be one about to be signed, to reduce its capabilities beforehand.
+    /// Proofs which the UCAN doesn't embed are sourced from the store.
+    pub fn from_resolved(ucan: Ucan, proofs: Vec<ProofChain>) -> Result<ProofChain> {
+        let mut linked = Vec::new();
+        for mut proof in proofs.into_iter() {
+            proof.validate_link_to(&ucan)?;
+            // the CID the UCAN refers to the proof by, maybe by another hasher
+            let cid = ucan
+                .proofs()
+                .iter()
+                .flatten()
+                .filter_map(|cid| Cid::try_from(cid.as_str()).ok())
+                .find(|cid| {
+                    Code::try_from(cid.hash().code())
+                        .ok()
+                        .and_then(|hasher| proof.ucan.to_cid(hasher).ok())
+                        .as_ref()
+                        == Some(cid)
+                })
+                .ok_or_else(|| {
+                    anyhow!("Proof {} isn't a proof of the UCAN", proof.ucan.issuer())
+                })?;
+            proof.source = match ucan.require_token(&cid) {
+                Some(_) => ProofSource::Facts,
+                None => ProofSource::Store,
+            };
+            linked.push(proof);
+        }
+        let redelegations = Self::redelegations(&ucan, &linked, &[])?;
+
+        Ok(ProofChain {
+            ucan,
+            proofs: linked,
+            redelegations,
+            source: ProofSource::Token,
+        })
+    }
+
+    // the CIDs of the proofs which the UCAN re-delegates with "ucan:"
+    // capabilities, but for the missing ones
+    fn redelegations(ucan: &Ucan, proofs: &[ProofChain], missing: &[Cid]) -> Result<BTreeSet<Cid>> {
         let mut redelegations = BTreeSet::<Cid>::new();
 
         for capability in ucan
@@ -94,15 +238,17 @@ impl ProofChain {
                             }
                         }
                         ProofSelection::Cid(cid) => {
-                            if proofs.iter().any(|proof| {
-                                if let Ok(proof_cid) = proof.ucan.to_cid(Self::default_hasher()) {
+                            // the CID may be produced by a non-default hasher
+                            let hasher = Code::try_from(cid.hash().code())?;
+                            if let Some(proof) = proofs.iter().find(|proof| {
+                                if let Ok(proof_cid) = proof.ucan.to_cid(hasher) {
                                     proof_cid == cid
                                 } else {
                                     false
                                 }
                             }) {
-                                redelegations.insert(cid);
-                            } else {
+                                redelegations.insert(proof.ucan.to_cid(Self::default_hasher())?);
+                            } else if !missing.contains(&cid) {
                                 return Err(anyhow!(
                                     "Unable to redelegate proof; CID not found {}",
                                     cid
@@ -110,11 +256,12 @@ impl ProofChain {
                             }
                         }
                         ProofSelection::Did(did) => {
-                            if let Some(proof) =
-                                proofs.iter().find(|proof| proof.ucan.issuer() == did)
-                            {
+                            if let Some(proof) = proofs.iter().find(|proof| {
+                                normalize_did(proof.ucan.issuer()) == normalize_did(&did)
+                            }) {
                                 redelegations.insert(proof.ucan.to_cid(Self::default_hasher())?);
-                            } else {
+                            } else if missing.is_empty() {
+                                // a missing proof may be the one of the DID
                                 return Err(anyhow!(
                                     "Unable to redelegate proof; DID not found {}",
                                     did
@@ -135,11 +282,7 @@ impl ProofChain {
             }
         }
 
-        Ok(ProofChain {
-            ucan,
-            proofs,
-            redelegations,
-        })
+        Ok(redelegations)
     }
 
     /// Instantiate a [ProofChain] from a [Cid], given a [UcanJwtStore] and [DidParser]
@@ -177,8 +320,8 @@ impl ProofChain {
     }
 
     fn validate_link_to(&self, ucan: &Ucan) -> Result<()> {
-        let audience = self.ucan.audience();
-        let issuer = ucan.issuer();
+        let audience = normalize_did(self.ucan.audience());
+        let issuer = normalize_did(ucan.issuer());
 
         match audience == issuer {
             true => match self.ucan.lifetime_encompasses(ucan) {
@@ -197,10 +340,36 @@ impl ProofChain {
         &self.ucan
     }
 
+    pub fn source(&self) -> ProofSource {
+        self.source
+    }
+
     pub fn proofs(&self) -> &Vec<ProofChain> {
         &self.proofs
     }
 
+    /// Returns the chain without the proofs of the given CID (by the default
+    /// hasher) sourced from the store, wherever they are in the chain: the
+    /// chain resolved from a bundle leaving out that token, with
+    /// [ResolveOptions::skip_missing].
+    pub fn without_stored_proof(&self, cid: &Cid) -> ProofChain {
+        let proofs = self
+            .proofs
+            .iter()
+            .filter(|proof| {
+                proof.source != ProofSource::Store
+                    || proof.ucan.to_cid(Self::default_hasher()).ok().as_ref() != Some(cid)
+            })
+            .map(|proof| proof.without_stored_proof(cid))
+            .collect();
+        ProofChain {
+            ucan: self.ucan.clone(),
+            proofs,
+            redelegations: self.redelegations.clone(),
+            source: self.source,
+        }
+    }
+
     pub fn reduce_capabilities<Semantics, S, A>(
         &self,
         semantics: &Semantics,
@@ -210,68 +379,143 @@ impl ProofChain {
         S: Scope,
         A: Ability,
     {
+        // truncation never fails
+        self.reduce_capabilities_with(semantics, ParseFailureMode::Truncate, &mut Vec::new())
+            .unwrap_or_default()
+    }
+
+    /// Reduces capabilities, handling capabilities which the semantics can't
+    /// parse by the given mode. Skipped capabilities are added to `warnings`.
+    ///
+    /// The UCAN of the chain is the invocation: it may claim capabilities
+    /// which can't be re-delegated anymore (see
+    /// [crate::capability::MAX_DEPTH_CAVEAT]).
+    pub fn reduce_capabilities_with<Semantics, S, A>(
+        &self,
+        semantics: &Semantics,
+        mode: ParseFailureMode,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<CapabilityInfo<S, A>>>
+    where
+        Semantics: CapabilitySemantics<S, A>,
+        S: Scope,
+        A: Ability,
+    {
+        self.reduce_link_capabilities(semantics, mode, warnings, true)
+    }
+
+    /// Reduces capabilities like [ProofChain::reduce_capabilities_with], the
+    /// UCAN of the chain being a proof of another UCAN: a capability with no
+    /// remaining depth can't be claimed by a delegation of it, only by an
+    /// invocation.
+    pub fn reduce_proof_capabilities_with<Semantics, S, A>(
+        &self,
+        semantics: &Semantics,
+        mode: ParseFailureMode,
+        warnings: &mut Vec<String>,
+    ) -> Result<Vec<CapabilityInfo<S, A>>>
+    where
+        Semantics: CapabilitySemantics<S, A>,
+        S: Scope,
+        A: Ability,
+    {
+        self.reduce_link_capabilities(semantics, mode, warnings, false)
+    }
+
+    fn reduce_link_capabilities<Semantics, S, A>(
+        &self,
+        semantics: &Semantics,
+        mode: ParseFailureMode,
+        warnings: &mut Vec<String>,
+        invocation: bool,
+    ) -> Result<Vec<CapabilityInfo<S, A>>>
+    where
+        Semantics: CapabilitySemantics<S, A>,
+        S: Scope,
+        A: Ability,
+    {
+        // Capabilities of proofs which this UCAN may claim: all for the
+        // invocation, otherwise only those which can be re-delegated
+        let claimable = |info: &CapabilityInfo<S, A>| invocation || info.remaining_depth != Some(0);
+
         // Get the set of inherited attenuations (excluding redelegations)
         // before further attenuating by own lifetime and capabilities:
-        let ancestral_capability_infos: Vec<CapabilityInfo<S, A>> = self
-            .proofs
-            .iter()
-            .flat_map(|ancestor_chain| {
-                if let Ok(cid) = ancestor_chain.ucan.to_cid(Self::default_hasher()) {
-                    if self.redelegations.contains(&cid) {
-                        Vec::new()
-                    } else {
-                        ancestor_chain.reduce_capabilities(semantics)
-                    }
-                } else {
-                    // skip if error
-                    Vec::new()
+        let mut ancestral_capability_infos: Vec<CapabilityInfo<S, A>> = Vec::new();
+        for ancestor_chain in self.proofs.iter() {
+            if let Ok(cid) = ancestor_chain.ucan.to_cid(Self::default_hasher()) {
+                if !self.redelegations.contains(&cid) {
+                    ancestral_capability_infos.extend(
+                        ancestor_chain
+                            .reduce_link_capabilities(semantics, mode, warnings, false)?
+                            .into_iter()
+                            .filter(claimable),
+                    );
                 }
-            })
-            .collect();
+            }
+            // skip if error
+        }
 
         // Get the set of capabilities that are blanket redelegated from
         // ancestor proofs (via the ucan: resource):
-        let mut redelegated_capability_infos: Vec<CapabilityInfo<S, A>> = self
-            .redelegations
-            .iter()
-            .flat_map(|redelegation_cid| {
-                let proof_chain = self.proofs.iter().find(|proof| {
-                    if let Ok(cid) = proof.ucan.to_cid(Self::default_hasher()) {
-                        &cid == redelegation_cid
-                    } else {
-                        false
-                    }
-                });
-                if let Some(proof_chain) = proof_chain {
+        let mut redelegated_capability_infos: Vec<CapabilityInfo<S, A>> = Vec::new();
+        for redelegation_cid in self.redelegations.iter() {
+            let proof_chain = self.proofs.iter().find(|proof| {
+                if let Ok(cid) = proof.ucan.to_cid(Self::default_hasher()) {
+                    &cid == redelegation_cid
+                } else {
+                    false
+                }
+            });
+            if let Some(proof_chain) = proof_chain {
+                redelegated_capability_infos.extend(
                     proof_chain
-                        .reduce_capabilities(semantics)
+                        .reduce_link_capabilities(semantics, mode, warnings, false)?
                         .into_iter()
+                        .filter(claimable)
                         .map(|mut info| {
                             // Redelegated capabilities should be attenuated by
                             // this UCAN's lifetime
                             info.not_before = *self.ucan.not_before();
                             info.expires_at = *self.ucan.expires_at();
+                            info.remaining_depth =
+                                info.remaining_depth.map(|depth| depth.saturating_sub(1));
                             info
-                        })
-                        .collect()
-                } else {
-                    Vec::new()
-                }
-            })
-            .collect();
+                        }),
+                );
+            }
+        }
 
-        let self_capabilities_iter = self
-            .ucan
-            .capabilities()
-            .iter()
-            .map_while(|data| semantics.parse_capability(&data));
+        let issuer = normalize_did(self.ucan.issuer());
+        let mut self_capabilities = Vec::new();
+        for data in self.ucan.capabilities().iter() {
+            match semantics.parse_issued_capability(&data, issuer) {
+                Some(capability) => self_capabilities.push(capability),
+                None => match mode {
+                    ParseFailureMode::Truncate => break,
+                    ParseFailureMode::Skip => warnings.push(format!(
+                        r#"Skipped capability "{} {}" issued by {}"#,
+                        data.resource, data.ability, issuer
+                    )),
+                    ParseFailureMode::Fail => {
+                        return Err(anyhow!(
+                            r#"Unable to parse capability "{} {}" issued by {}"#,
+                            data.resource,
+                            data.ability,
+                            issuer
+                        ))
+                    }
+                },
+            }
+        }
+        let self_capabilities_iter = self_capabilities.into_iter();
 
         // Get the claimed attenuations of this ucan, cross-checking ancestral
         // attenuations to discover the originating authority
         let mut self_capability_infos: Vec<CapabilityInfo<S, A>> = match self.proofs.len() {
             0 => self_capabilities_iter
                 .map(|capability| CapabilityInfo {
-                    originators: BTreeSet::from_iter(vec![self.ucan.issuer().to_string()]),
+                    originators: BTreeSet::from_iter(vec![issuer.to_string()]),
+                    remaining_depth: max_depth(&capability),
                     capability,
                     not_before: *self.ucan.not_before(),
                     expires_at: *self.ucan.expires_at(),
@@ -280,11 +524,19 @@ impl ProofChain {
             _ => self_capabilities_iter
                 .map(|capability| {
                     let mut originators = BTreeSet::<String>::new();
+                    // the depth allowed by the ancestral capabilities
+                    let mut ancestral_depth = Some(0);
 
                     for ancestral_capability_info in ancestral_capability_infos.iter() {
                         match ancestral_capability_info.capability.enables(&capability) {
                             true => {
-                                originators.extend(ancestral_capability_info.originators.clone())
+                                originators.extend(ancestral_capability_info.originators.clone());
+                                ancestral_depth = max_remaining_depth(
+                                    ancestral_depth,
+                                    ancestral_capability_info
+                                        .remaining_depth
+                                        .map(|depth| depth.saturating_sub(1)),
+                                );
                             }
                             // true => return Some(capability),
                             false => continue,
@@ -294,10 +546,15 @@ impl ProofChain {
                     // If there are no related ancestral capability, then this
                     // link in the chain is considered the first originator
                     if originators.is_empty() {
-                        originators.insert(self.ucan.issuer().to_string());
+                        originators.insert(issuer.to_string());
+                        ancestral_depth = None;
                     }
 
                     CapabilityInfo {
+                        remaining_depth: min_remaining_depth(
+                            ancestral_depth,
+                            max_depth(&capability),
+                        ),
                         capability,
                         originators,
                         not_before: *self.ucan.not_before(),
@@ -322,6 +579,10 @@ impl ProofChain {
                     remaining_capability_info
                         .originators
                         .extend(capability_info.originators);
+                    remaining_capability_info.remaining_depth = max_remaining_depth(
+                        remaining_capability_info.remaining_depth,
+                        capability_info.remaining_depth,
+                    );
                     continue 'merge;
                 }
             }
@@ -329,7 +590,7 @@ impl ProofChain {
             merged_capability_infos.push(capability_info);
         }
 
-        merged_capability_infos
+        Ok(merged_capability_infos)
     }
 
     /// Returns the default hasher ([Code::Blake3_256]) used for [Cid] encodings.
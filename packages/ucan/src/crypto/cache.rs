@@ -0,0 +1,99 @@
+use multihash_codetable::{Code, MultihashDigest};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+type SignatureCacheKey = Vec<u8>;
+
+#[derive(Default)]
+struct SignatureCacheState {
+    tick: u64,
+    entries: HashMap<SignatureCacheKey, u64>,
+    order: BTreeMap<u64, SignatureCacheKey>,
+}
+
+/// A bounded LRU cache of successful signature verifications, keyed by a hash
+/// of the issuer DID, the signed data and the signature. It can be shared
+/// between [`super::did::DidParser`] instances to skip verifying the same
+/// signatures again.
+pub struct SignatureCache {
+    capacity: usize,
+    state: Mutex<SignatureCacheState>,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: usize) -> Self {
+        SignatureCache {
+            capacity,
+            state: Mutex::new(SignatureCacheState::default()),
+        }
+    }
+
+    pub fn key(issuer: &str, signed_data: &[u8], signature: &[u8]) -> SignatureCacheKey {
+        let data = [issuer.as_bytes(), &[0], signed_data, &[0], signature].concat();
+        Code::Sha2_256.digest(&data).digest().to_vec()
+    }
+
+    /// Returns true if the signature was verified before, marking it as
+    /// recently used.
+    pub fn contains(&self, key: &SignatureCacheKey) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        state.tick += 1;
+        let tick = state.tick;
+        match state.entries.get_mut(key) {
+            Some(entry) => {
+                let previous = std::mem::replace(entry, tick);
+                state.order.remove(&previous);
+                state.order.insert(tick, key.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remembers a successfully verified signature, evicting the least
+    /// recently used one if the cache is full.
+    pub fn insert(&self, key: SignatureCacheKey) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some(previous) = state.entries.insert(key.clone(), tick) {
+            state.order.remove(&previous);
+        }
+        state.order.insert(tick, key);
+        while state.entries.len() > self.capacity {
+            match state.order.pop_first() {
+                Some((_, oldest)) => {
+                    state.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.entries.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.entries.clear();
+            state.order.clear();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::KeyMaterial;
+use super::{KeyMaterial, SignatureCache};
 use anyhow::{anyhow, Result};
 use std::{collections::BTreeMap, sync::Arc};
 
@@ -18,6 +18,15 @@ pub const BLS12381G2_MAGIC_BYTES: &[u8] = &[0xeb, 0x01];
 pub const P256_MAGIC_BYTES: &[u8] = &[0x80, 0x24];
 pub const SECP256K1_MAGIC_BYTES: &[u8] = &[0xe7, 0x1];
 
+/// Returns the DID of a DID URL, dropping its path, query and fragment, e.g.
+/// "did:key:z6Mk...#z6Mk..." becomes "did:key:z6Mk...".
+pub fn normalize_did(did: &str) -> &str {
+    match did.find(&['/', '?', '#'][..]) {
+        Some(index) => &did[..index],
+        None => did,
+    }
+}
+
 /// A parser that is able to convert from a DID string into a corresponding
 /// [`KeyMaterial`] implementation. The parser extracts the signature
 /// magic bytes from a given DID and tries to match them to a corresponding
@@ -25,6 +34,7 @@ pub const SECP256K1_MAGIC_BYTES: &[u8] = &[0xe7, 0x1];
 pub struct DidParser {
     key_constructors: KeyConstructors,
     key_cache: KeyCache,
+    signature_cache: Option<Arc<SignatureCache>>,
 }
 
 impl DidParser {
@@ -36,10 +46,22 @@ impl DidParser {
         DidParser {
             key_constructors,
             key_cache: BTreeMap::new(),
+            signature_cache: None,
         }
     }
 
+    /// Use a (shared) cache of successful signature verifications.
+    pub fn with_signature_cache(mut self, signature_cache: Arc<SignatureCache>) -> Self {
+        self.signature_cache = Some(signature_cache);
+        self
+    }
+
+    pub fn signature_cache(&self) -> Option<&Arc<SignatureCache>> {
+        self.signature_cache.as_ref()
+    }
+
     pub fn parse(&mut self, did: &str) -> Result<Arc<Box<dyn KeyMaterial>>> {
+        let did = normalize_did(did);
         if !did.starts_with(DID_KEY_PREFIX) {
             return Err(anyhow!("Expected valid did:key, got: {}", did));
         }
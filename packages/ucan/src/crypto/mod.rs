@@ -1,6 +1,8 @@
+mod cache;
 pub mod did;
 mod key;
 mod signature;
 
+pub use cache::*;
 pub use key::*;
 pub use signature::*;
@@ -0,0 +1,36 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use multihash_codetable::{Code, MultihashDigest};
+use rand::Rng;
+use serde_json::Value;
+
+/// The fact holding digests of hidden facts, by name. A hidden fact can be
+/// read only by holders of its disclosure (a salted `[salt, name, value]`
+/// array, base64url-encoded).
+pub const DIGESTS_FACT: &str = "_sd";
+
+/// Makes a disclosure of the fact, with a random salt.
+pub fn make_disclosure(name: &str, value: &Value) -> Result<String> {
+    let salt = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .encode(rand::thread_rng().gen::<[u8; 16]>());
+    let disclosure = serde_json::to_vec(&serde_json::json!([salt, name, value]))?;
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(disclosure))
+}
+
+/// Returns the digest of the disclosure (base64url-encoded SHA-256).
+pub fn digest_disclosure(disclosure: &str) -> String {
+    let digest = Code::Sha2_256.digest(disclosure.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(digest.digest())
+}
+
+/// Returns the name and the value of the disclosed fact.
+pub fn open_disclosure(disclosure: &str) -> Result<(String, Value)> {
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(disclosure)?;
+    match serde_json::from_slice::<Value>(&bytes)? {
+        Value::Array(items) if items.len() == 3 && items[0].is_string() => match &items[1] {
+            Value::String(name) => Ok((name.to_owned(), items[2].clone())),
+            _ => Err(anyhow!("invalid disclosure")),
+        },
+        _ => Err(anyhow!("invalid disclosure")),
+    }
+}
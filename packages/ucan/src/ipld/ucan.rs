@@ -71,6 +71,7 @@ impl TryFrom<&UcanIpld> for Ucan {
         let header = UcanHeader {
             alg: algorithm.to_string(),
             typ: "JWT".into(),
+            extra: FactsMap::new(),
         };
 
         let payload = UcanPayload {
@@ -86,6 +87,7 @@ impl TryFrom<&UcanIpld> for Ucan {
                 .prf
                 .clone()
                 .map(|prf| prf.iter().map(|cid| cid.to_string()).collect()),
+            extra: FactsMap::new(),
         };
 
         let signed_data = format!(
@@ -82,11 +82,15 @@ pub mod time;
 pub mod builder;
 pub mod capability;
 pub mod chain;
+pub mod disclosure;
 pub mod ipld;
 pub mod serde;
 pub mod store;
 pub mod ucan;
 pub use self::ucan::Ucan;
 
+/// The version of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[cfg(test)]
 mod tests;
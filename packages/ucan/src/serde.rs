@@ -1,13 +1,14 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use base64::Engine;
 use libipld_core::{
     codec::{Decode, Encode},
     ipld::Ipld,
-    serde::{from_ipld, to_ipld},
+    serde::to_ipld,
 };
 use libipld_json::DagJsonCodec;
 use serde::{de::DeserializeOwned, Serialize, Serializer};
-use std::io::Cursor;
+use serde_json::Value;
+use std::{fmt::Write, io::Cursor};
 
 /// Utility function to enforce lower-case string values when serializing
 pub fn ser_to_lower_case<S>(string: &str, serializer: S) -> Result<S::Ok, S::Error>
@@ -30,10 +31,44 @@ pub trait DagJson: Serialize + DeserializeOwned {
 
     fn from_dag_json(json_bytes: &[u8]) -> Result<Self> {
         let ipld = Ipld::decode(DagJsonCodec, &mut Cursor::new(json_bytes))?;
-        Ok(from_ipld(ipld)?)
+        // JSON values (like caveats and facts) can't be deserialized from
+        // IPLD integers (i128) directly
+        Ok(serde_json::from_value(ipld_to_json(ipld)?)?)
     }
 }
 
+/// Converts IPLD into JSON, links and bytes as they are encoded in DAG-JSON.
+fn ipld_to_json(ipld: Ipld) -> Result<Value> {
+    Ok(match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(b) => Value::Bool(b),
+        Ipld::Integer(i) => match (i64::try_from(i), u64::try_from(i)) {
+            (Ok(i), _) => Value::from(i),
+            (_, Ok(u)) => Value::from(u),
+            _ => return Err(anyhow!("Integer {} is out of range", i)),
+        },
+        Ipld::Float(f) => serde_json::Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("Invalid float {}", f))?,
+        Ipld::String(s) => Value::String(s),
+        Ipld::Bytes(bytes) => serde_json::json!({
+            "/": { "bytes": base64::engine::general_purpose::STANDARD_NO_PAD.encode(bytes) }
+        }),
+        Ipld::List(items) => Value::Array(
+            items
+                .into_iter()
+                .map(ipld_to_json)
+                .collect::<Result<Vec<Value>>>()?,
+        ),
+        Ipld::Map(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| Ok((key, ipld_to_json(value)?)))
+                .collect::<Result<serde_json::Map<String, Value>>>()?,
+        ),
+        Ipld::Link(cid) => serde_json::json!({ "/": cid.to_string() }),
+    })
+}
+
 impl<T> DagJson for T where T: Serialize + DeserializeOwned {}
 
 /// Helper trait to encode structs as base64 as part of creating a JWT
@@ -41,6 +76,118 @@ pub trait Base64Encode: DagJson {
     fn jwt_base64_encode(&self) -> Result<String> {
         Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.to_dag_json()?))
     }
+
+    /// Encodes as base64 of the canonical JSON (RFC 8785) of the DAG-JSON
+    /// encoding, so equal values always produce equal strings.
+    fn jwt_base64_encode_canonical(&self) -> Result<String> {
+        let json = canonicalize_json(&serde_json::from_slice(&self.to_dag_json()?)?);
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json))
+    }
 }
 
 impl<T> Base64Encode for T where T: DagJson {}
+
+/// Serializes a JSON value with the JSON Canonicalization Scheme (RFC 8785):
+/// no whitespace, object keys sorted by their UTF-16 code units, and numbers
+/// formatted as ECMAScript does. Integers are written exactly, even past 2^53
+/// where ECMAScript would round them.
+pub fn canonicalize_json(value: &Value) -> String {
+    let mut json = String::new();
+    write_canonical_json(value, &mut json);
+    json
+}
+
+fn write_canonical_json(value: &Value, json: &mut String) {
+    match value {
+        Value::Null => json.push_str("null"),
+        Value::Bool(b) => json.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_i64() || n.is_u64() => json.push_str(&n.to_string()),
+        Value::Number(n) => match n.as_f64() {
+            Some(n) => write_canonical_number(n, json),
+            None => json.push_str(&n.to_string()),
+        },
+        Value::String(s) => write_canonical_string(s, json),
+        Value::Array(items) => {
+            json.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_canonical_json(item, json);
+            }
+            json.push(']');
+        }
+        Value::Object(obj) => {
+            let mut entries: Vec<(&String, &Value)> = obj.iter().collect();
+            entries.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+            json.push('{');
+            for (index, (key, value)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    json.push(',');
+                }
+                write_canonical_string(key, json);
+                json.push(':');
+                write_canonical_json(value, json);
+            }
+            json.push('}');
+        }
+    }
+}
+
+fn write_canonical_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\u{8}' => json.push_str("\\b"),
+            '\t' => json.push_str("\\t"),
+            '\n' => json.push_str("\\n"),
+            '\u{c}' => json.push_str("\\f"),
+            '\r' => json.push_str("\\r"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+// ECMAScript Number.prototype.toString()
+fn write_canonical_number(n: f64, json: &mut String) {
+    if n == 0.0 || !n.is_finite() {
+        // JSON has no NaN and infinities
+        json.push('0');
+        return;
+    }
+    if n < 0.0 {
+        json.push('-');
+    }
+    // the shortest representation, like "1.2345e-7"
+    let scientific = format!("{:e}", n.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let k = digits.len() as i32;
+    let n = exponent + 1;
+    if k <= n && n <= 21 {
+        json.push_str(&digits);
+        json.push_str(&"0".repeat((n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        json.push_str(&digits[..n as usize]);
+        json.push('.');
+        json.push_str(&digits[n as usize..]);
+    } else if -6 < n && n <= 0 {
+        json.push_str("0.");
+        json.push_str(&"0".repeat(-n as usize));
+        json.push_str(&digits);
+    } else {
+        json.push_str(&digits[..1]);
+        if k > 1 {
+            json.push('.');
+            json.push_str(&digits[1..]);
+        }
+        let _ = write!(json, "e{}{}", if n > 0 { "+" } else { "-" }, (n - 1).abs());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{time::now, ucan::Ucan};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use cid::Cid;
@@ -8,7 +9,7 @@ use libipld_core::{
 };
 use multihash_codetable::{Code, MultihashDigest};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
     io::Cursor,
     sync::{Arc, Mutex},
 };
@@ -94,9 +95,151 @@ impl<U> UcanJwtStore for U where U: UcanStore<RawCodec> {}
 /// codec. This will serve for basic use cases and tests, but it is
 /// recommended that a store that persists to disk be used in most
 /// practical use cases.
+///
+/// UCANs written with [MemoryStore::write_ucan] are forgotten once expired,
+/// and a store created with [MemoryStore::with_capacity] forgets the oldest
+/// blocks past its capacity.
 #[derive(Clone, Default, Debug)]
 pub struct MemoryStore {
-    dags: Arc<Mutex<HashMap<Cid, Vec<u8>>>>,
+    dags: Arc<Mutex<MemoryBlocks>>,
+    capacity: Option<usize>,
+}
+
+#[derive(Default, Debug)]
+struct MemoryBlocks {
+    blocks: HashMap<Cid, MemoryBlock>,
+    // write sequence number -> CID, oldest first
+    written: BTreeMap<u64, Cid>,
+    next_sequence: u64,
+    // (expiration, CID) of the blocks written with an expiration
+    expirations: BTreeSet<(u64, Cid)>,
+}
+
+#[derive(Debug)]
+struct MemoryBlock {
+    sequence: u64,
+    expiration: Option<u64>,
+    bytes: Vec<u8>,
+}
+
+impl MemoryBlocks {
+    fn insert(
+        &mut self,
+        cid: Cid,
+        bytes: Vec<u8>,
+        expiration: Option<u64>,
+        capacity: Option<usize>,
+    ) {
+        if let Some(block) = self.blocks.get_mut(&cid) {
+            if block.expiration.is_none() {
+                if let Some(expiration) = expiration {
+                    block.expiration = Some(expiration);
+                    self.expirations.insert((expiration, cid));
+                }
+            }
+            return;
+        }
+        if let Some(capacity) = capacity {
+            while self.blocks.len() >= capacity.max(1) {
+                match self.written.first_key_value() {
+                    Some((_, oldest)) => {
+                        let oldest = *oldest;
+                        self.remove(&oldest);
+                    }
+                    None => break,
+                }
+            }
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.written.insert(sequence, cid);
+        if let Some(expiration) = expiration {
+            self.expirations.insert((expiration, cid));
+        }
+        self.blocks.insert(
+            cid,
+            MemoryBlock {
+                sequence,
+                expiration,
+                bytes,
+            },
+        );
+    }
+
+    fn remove(&mut self, cid: &Cid) {
+        if let Some(block) = self.blocks.remove(cid) {
+            self.written.remove(&block.sequence);
+            if let Some(expiration) = block.expiration {
+                self.expirations.remove(&(expiration, *cid));
+            }
+        }
+    }
+
+    fn prune_expired(&mut self, now: u64) {
+        while let Some((expiration, cid)) = self.expirations.first().copied() {
+            if expiration >= now {
+                break;
+            }
+            self.remove(&cid);
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates a store keeping at most `capacity` blocks, forgetting the
+    /// oldest written ones first.
+    pub fn with_capacity(capacity: usize) -> Self {
+        MemoryStore {
+            dags: Default::default(),
+            capacity: Some(capacity),
+        }
+    }
+
+    /// Returns all the stored blocks with their CIDs.
+    pub fn blocks(&self) -> Result<Vec<(Cid, Vec<u8>)>> {
+        let dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
+        Ok(dags
+            .blocks
+            .iter()
+            .map(|(cid, block)| (*cid, block.bytes.clone()))
+            .collect())
+    }
+
+    /// Writes the encoded UCAN, to be forgotten once it expires. Forgets the
+    /// UCANs already expired.
+    pub fn write_ucan(&mut self, ucan: &Ucan) -> Result<Cid> {
+        let codec = RawCodec;
+        let block = codec.encode(&Ipld::Bytes(ucan.encode()?.into_bytes()))?;
+        let cid = Cid::new_v1(codec.into(), Code::Blake3_256.digest(&block));
+
+        let mut dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
+        dags.prune_expired(now());
+        dags.insert(cid, block, *ucan.expires_at(), self.capacity);
+
+        Ok(cid)
+    }
+
+    /// Returns the CIDs of the UCANs written with [MemoryStore::write_ucan]
+    /// which expire after `after` and until `until`, with their expirations,
+    /// soonest first.
+    pub fn expiring(&self, after: u64, until: u64) -> Result<Vec<(Cid, u64)>> {
+        let dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
+        Ok(dags
+            .expirations
+            .iter()
+            .skip_while(|(expiration, _)| *expiration <= after)
+            .take_while(|(expiration, _)| *expiration <= until)
+            .map(|(expiration, cid)| (*cid, *expiration))
+            .collect())
+    }
+
+    /// Forgets the UCANs written with [MemoryStore::write_ucan] which expired
+    /// before `now`.
+    pub fn prune_expired(&self, now: u64) -> Result<()> {
+        let mut dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
+        dags.prune_expired(now);
+        Ok(())
+    }
 }
 
 #[cfg_attr(not(target_arch = "wasm32"), async_trait)]
@@ -106,8 +249,8 @@ impl UcanStore<RawCodec> for MemoryStore {
         let codec = RawCodec;
         let dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
 
-        Ok(match dags.get(cid) {
-            Some(bytes) => Some(T::decode(codec, &mut Cursor::new(bytes))?),
+        Ok(match dags.blocks.get(cid) {
+            Some(block) => Some(T::decode(codec, &mut Cursor::new(&block.bytes))?),
             None => None,
         })
     }
@@ -121,8 +264,47 @@ impl UcanStore<RawCodec> for MemoryStore {
         let cid = Cid::new_v1(codec.into(), Code::Blake3_256.digest(&block));
 
         let mut dags = self.dags.lock().map_err(|_| anyhow!("poisoned mutex!"))?;
-        dags.insert(cid, block);
+        dags.insert(cid, block, None, self.capacity);
 
         Ok(cid)
     }
 }
+
+/// A store writing to a [MemoryStore] of its own, and reading from it first,
+/// then from another store: to resolve proofs with tokens which must not be
+/// kept in the other store.
+#[derive(Clone, Debug)]
+pub struct LayeredStore<S> {
+    top: MemoryStore,
+    bottom: S,
+}
+
+impl<S> LayeredStore<S> {
+    pub fn new(bottom: S) -> Self {
+        LayeredStore {
+            top: MemoryStore::default(),
+            bottom,
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<S> UcanStore<RawCodec> for LayeredStore<S>
+where
+    S: UcanStore<RawCodec>,
+{
+    async fn read<T: Decode<RawCodec>>(&self, cid: &Cid) -> Result<Option<T>> {
+        if let Some(value) = self.top.read(cid).await? {
+            return Ok(Some(value));
+        }
+        self.bottom.read(cid).await
+    }
+
+    async fn write<T: Encode<RawCodec> + UcanStoreConditionalSend + core::fmt::Debug>(
+        &mut self,
+        token: T,
+    ) -> Result<Cid> {
+        self.top.write(token).await
+    }
+}
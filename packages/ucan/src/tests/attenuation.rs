@@ -215,7 +215,8 @@ pub async fn it_finds_the_right_proof_chain_for_the_originator() {
             originators: BTreeSet::from_iter(vec![identities.alice_did]),
             capability: send_email_as_alice,
             not_before: ucan.not_before().clone(),
-            expires_at: ucan.expires_at().clone()
+            expires_at: ucan.expires_at().clone(),
+            remaining_depth: None
         }
     );
 
@@ -225,7 +226,8 @@ pub async fn it_finds_the_right_proof_chain_for_the_originator() {
             originators: BTreeSet::from_iter(vec![identities.bob_did]),
             capability: send_email_as_bob,
             not_before: ucan.not_before().clone(),
-            expires_at: ucan.expires_at().clone()
+            expires_at: ucan.expires_at().clone(),
+            remaining_depth: None
         }
     );
 }
@@ -305,7 +307,8 @@ pub async fn it_reports_all_chain_options() {
             originators: BTreeSet::from_iter(vec![identities.alice_did, identities.bob_did]),
             capability: send_email_as_alice,
             not_before: ucan.not_before().clone(),
-            expires_at: ucan.expires_at().clone()
+            expires_at: ucan.expires_at().clone(),
+            remaining_depth: None
         }
     );
 }
@@ -5,11 +5,14 @@ use crate::{
     capability::{Capabilities, Capability, CapabilitySemantics},
     chain::ProofChain,
     crypto::did::DidParser,
+    disclosure::{digest_disclosure, make_disclosure, open_disclosure, DIGESTS_FACT},
     store::UcanJwtStore,
-    tests::fixtures::{
-        Blake2bMemoryStore, EmailSemantics, Identities, WNFSSemantics, SUPPORTED_KEYS,
+    tests::{
+        fixtures::{Blake2bMemoryStore, EmailSemantics, Identities, WNFSSemantics, SUPPORTED_KEYS},
+        helpers::jwt_part,
     },
     time::now,
+    ucan::Ucan,
 };
 use did_key::PatchedKeyPair;
 use multihash_codetable::Code;
@@ -103,6 +106,45 @@ async fn it_builds_with_lifetime_in_seconds() {
     assert!(ucan.expires_at().unwrap() > (now() + 290));
 }
 
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_builds_with_a_given_nonce() {
+    let identities = Identities::new().await;
+
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .with_nonce()
+        .with_nonce_value("abc")
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    assert_eq!(ucan.nonce(), &Some("abc".to_owned()));
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_generates_the_nonce_before_signing() {
+    let identities = Identities::new().await;
+
+    let signable = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .with_nonce()
+        .build()
+        .unwrap();
+    let nonce = signable.nonce.clone();
+    assert!(nonce.is_some());
+
+    let ucan = signable.sign().await.unwrap();
+    assert_eq!(ucan.nonce(), &nonce);
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
 #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
 async fn it_prevents_duplicate_proofs() {
@@ -159,6 +201,46 @@ async fn it_prevents_duplicate_proofs() {
     )
 }
 
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_does_not_duplicate_delegated_proofs() {
+    let identities = Identities::new().await;
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let next_ucan = UcanBuilder::default()
+        .issued_by(&identities.bob_key)
+        .for_audience(identities.mallory_did.as_str())
+        .with_lifetime(30)
+        .with_add_proof_facts(true)
+        .witnessed_by(&ucan, None)
+        .unwrap()
+        .delegating_from(&ucan, None)
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let cid = ucan
+        .to_cid(UcanBuilder::<PatchedKeyPair>::default_hasher())
+        .unwrap();
+    assert_eq!(next_ucan.proofs(), &Some(vec![cid.to_string()]));
+    assert!(next_ucan
+        .capabilities()
+        .get(&format!("ucan:{cid}"))
+        .is_some());
+    assert!(next_ucan.require_token(&cid).is_some());
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
 #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
 pub async fn it_can_use_custom_hasher() {
@@ -205,3 +287,100 @@ pub async fn it_can_use_custom_hasher() {
 
     assert!(valid_chain.is_ok());
 }
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_hides_facts_until_disclosed() {
+    let identities = Identities::new().await;
+
+    let signable = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .with_fact("tenant", json!("acme"))
+        .with_hidden_fact("email", "alice@example.com")
+        .unwrap()
+        .build()
+        .unwrap();
+    let disclosures = signable.disclosures.clone();
+    let ucan = signable.sign().await.unwrap();
+
+    let facts = ucan.facts().clone().unwrap();
+    assert_eq!(facts.get("tenant"), Some(&json!("acme")));
+    assert!(facts.get("email").is_none());
+
+    let digests = facts.get(DIGESTS_FACT).unwrap().as_object().unwrap();
+    let email = disclosures.get("email").unwrap();
+    assert_eq!(
+        open_disclosure(email).unwrap(),
+        ("email".to_owned(), json!("alice@example.com"))
+    );
+    assert_eq!(digests.get("email"), Some(&json!(digest_disclosure(email))));
+
+    let forged = make_disclosure("email", &json!("mallory@example.com")).unwrap();
+    assert_ne!(
+        digests.get("email"),
+        Some(&json!(digest_disclosure(&forged)))
+    );
+    assert!(open_disclosure("e30").is_err());
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_adds_custom_header_fields() {
+    let identities = Identities::new().await;
+
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .with_type("ucan+jwt")
+        .with_header_field("kid", "key-1")
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let token = ucan.encode().unwrap();
+    let decoded = Ucan::try_from(token.as_str()).unwrap();
+    assert_eq!(decoded.header().typ, "ucan+jwt");
+    assert_eq!(decoded.header().extra.get("kid"), Some(&json!("key-1")));
+    assert_eq!(decoded.encode().unwrap(), token);
+
+    let (canonical_token, _) = decoded.canonical_encode().unwrap();
+    assert!(jwt_part(&canonical_token, 0).contains(r#""kid":"key-1""#));
+
+    assert!(UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .with_header_field("alg", "none")
+        .is_err());
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+async fn it_adds_extra_claims() {
+    let identities = Identities::new().await;
+
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(30)
+        .with_extra_claim("jti", "token-1")
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let token = ucan.encode().unwrap();
+    let decoded = Ucan::try_from(token.as_str()).unwrap();
+    assert_eq!(decoded.extra_claims().get("jti"), Some(&json!("token-1")));
+
+    assert!(UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .with_extra_claim("aud", "did:key:z")
+        .is_err());
+}
@@ -89,3 +89,32 @@ fn it_filters_out_empty_caveats_when_iterating() {
         "iter() filters out capabilities with empty caveats"
     );
 }
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+fn it_locates_all_errors_of_non_compliant_json() {
+    let errors = Capabilities::validate(&json!({
+        "resource:foo": { "ability/read": [{}, 1], "ability/write": {} },
+        "resource:bar": {},
+    }));
+
+    assert_eq!(
+        errors
+            .iter()
+            .map(|error| (
+                error.resource.as_deref(),
+                error.ability.as_deref(),
+                error.caveat
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (Some("resource:bar"), None, None),
+            (Some("resource:foo"), Some("ability/read"), Some(1)),
+            (Some("resource:foo"), Some("ability/write"), None),
+        ]
+    );
+    assert_eq!(
+        errors[1].to_string(),
+        r#"Caveat must be an object: 1 (resource "resource:foo", ability "ability/read", caveat #1)"#
+    );
+}
@@ -1,11 +1,14 @@
 use super::fixtures::{Identities, SUPPORTED_KEYS};
 use crate::{
     builder::UcanBuilder,
-    chain::ProofChain,
-    crypto::did::DidParser,
+    chain::{ProofChain, ProofSource, ResolveOptions},
+    crypto::{did::DidParser, KeyMaterial},
     store::{MemoryStore, UcanJwtStore},
     time::now,
+    ucan::{Code, Ucan},
 };
+use base64::Engine;
+use serde_json::json;
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
@@ -61,6 +64,138 @@ pub async fn it_decodes_deep_ucan_chains() {
     );
 }
 
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_decodes_proofs_leniently() {
+    let identities = Identities::new().await;
+    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+    // issued by other JOSE tooling, with an "aud" array
+    let encode = |value: serde_json::Value| {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+    };
+    let signed_data = format!(
+        "{}.{}",
+        encode(json!({ "alg": "EdDSA", "typ": "JWT" })),
+        encode(json!({
+            "ucv": crate::ucan::UCAN_VERSION,
+            "iss": identities.alice_did,
+            "aud": [identities.bob_did],
+            "exp": now() + 60,
+            "cap": {}
+        }))
+    );
+    let signature = identities
+        .alice_key
+        .sign(signed_data.as_bytes())
+        .await
+        .unwrap();
+    let proof_token = format!(
+        "{}.{}",
+        signed_data,
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+    );
+    let proof_ucan = Ucan::from_str_lenient(&proof_token).unwrap();
+
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.bob_key)
+        .for_audience(identities.mallory_did.as_str())
+        .with_lifetime(50)
+        .witnessed_by(&proof_ucan, None)
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let mut store = MemoryStore::default();
+    store.write_token(&proof_token).await.unwrap();
+
+    assert!(
+        ProofChain::from_ucan(ucan.clone(), None, &mut did_parser, &store)
+            .await
+            .is_err()
+    );
+    let options = ResolveOptions {
+        lenient: true,
+        ..Default::default()
+    };
+    let chain = ProofChain::from_ucan_with(ucan, None, &mut did_parser, &store, options)
+        .await
+        .unwrap();
+    assert_eq!(chain.proofs()[0].ucan().audience(), &identities.bob_did);
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_skips_missing_proofs() {
+    let identities = Identities::new().await;
+    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+    let mut proofs = Vec::new();
+    for lifetime in [60, 61] {
+        proofs.push(
+            UcanBuilder::default()
+                .issued_by(&identities.alice_key)
+                .for_audience(identities.bob_did.as_str())
+                .with_lifetime(lifetime)
+                .build()
+                .unwrap()
+                .sign()
+                .await
+                .unwrap(),
+        );
+    }
+    let ucan = UcanBuilder::default()
+        .issued_by(&identities.bob_key)
+        .for_audience(identities.mallory_did.as_str())
+        .with_lifetime(50)
+        .witnessed_by(&proofs[0], None)
+        .unwrap()
+        .witnessed_by(&proofs[1], None)
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let mut store = MemoryStore::default();
+    store.write_ucan(&proofs[0]).unwrap();
+    assert!(
+        ProofChain::from_ucan(ucan.clone(), None, &mut did_parser, &store)
+            .await
+            .is_err()
+    );
+    let options = ResolveOptions {
+        skip_missing: true,
+        ..Default::default()
+    };
+    let chain = ProofChain::from_ucan_with(ucan.clone(), None, &mut did_parser, &store, options)
+        .await
+        .unwrap();
+    assert_eq!(chain.proofs().len(), 1);
+    assert_eq!(chain.proofs()[0].ucan(), &proofs[0]);
+
+    // the same chain as resolved without the missing proof
+    store.write_ucan(&proofs[1]).unwrap();
+    let chain = ProofChain::from_ucan(ucan.clone(), None, &mut did_parser, &store)
+        .await
+        .unwrap();
+    assert_eq!(chain.proofs().len(), 2);
+    let cid = proofs[1].to_cid(ProofChain::default_hasher()).unwrap();
+    let pruned = chain.without_stored_proof(&cid);
+    assert_eq!(pruned.proofs().len(), 1);
+    assert_eq!(pruned.proofs()[0].ucan(), &proofs[0]);
+
+    // and linked again from the resolved proofs
+    let linked = ProofChain::from_resolved(ucan, pruned.proofs().clone()).unwrap();
+    assert_eq!(linked.proofs().len(), 1);
+    assert_eq!(linked.proofs()[0].source(), ProofSource::Store);
+    assert!(ProofChain::from_resolved(proofs[1].clone(), pruned.proofs().clone()).is_err());
+}
+
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
 #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
 pub async fn it_fails_with_incorrect_chaining() {
@@ -258,3 +393,78 @@ pub async fn it_can_use_a_custom_timestamp_to_validate_a_ucan() {
 
     assert!(invalid_chain.is_err());
 }
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_verifies_embedded_proofs_match_their_cids() {
+    let identities = Identities::new().await;
+    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+    let leaf_ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(60)
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+    let other_leaf_ucan = UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_lifetime(70)
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap();
+
+    let embedded_token = UcanBuilder::default()
+        .issued_by(&identities.bob_key)
+        .for_audience(identities.mallory_did.as_str())
+        .with_lifetime(50)
+        .with_add_proof_facts(true)
+        .witnessed_by(&leaf_ucan, Some(Code::Sha2_256))
+        .unwrap()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap()
+        .encode()
+        .unwrap();
+
+    let store = MemoryStore::default();
+    let chain =
+        ProofChain::try_from_token_string(embedded_token.as_str(), None, &mut did_parser, &store)
+            .await
+            .unwrap();
+    assert_eq!(chain.source(), ProofSource::Token);
+    assert_eq!(chain.proofs().get(0).unwrap().source(), ProofSource::Facts);
+
+    // the embedded token is not the one the CID refers to
+    let leaf_cid = leaf_ucan.to_cid(Code::Sha2_256).unwrap().to_string();
+    let forged_token = UcanBuilder::default()
+        .issued_by(&identities.bob_key)
+        .for_audience(identities.mallory_did.as_str())
+        .with_lifetime(50)
+        .witnessed_by(&leaf_ucan, Some(Code::Sha2_256))
+        .unwrap()
+        .with_fact(
+            "prf",
+            json!({ leaf_cid: other_leaf_ucan.encode().unwrap() }),
+        )
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap()
+        .encode()
+        .unwrap();
+
+    let error =
+        ProofChain::try_from_token_string(forged_token.as_str(), None, &mut did_parser, &store)
+            .await
+            .unwrap_err();
+    assert!(error.to_string().contains("doesn't match"));
+}
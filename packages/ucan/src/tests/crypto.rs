@@ -25,3 +25,21 @@ mod did_from_keypair {
         assert_eq!(expected_did, result_did.as_str());
     }
 }
+
+mod normalize_did {
+    use crate::crypto::did::normalize_did;
+
+    #[test]
+    fn it_drops_did_url_parts() {
+        let did = "did:key:z6MkgYGF3thn8k1Fv4p4dWXKtsXCnLH7q9yw4QgNPULDmDKB";
+        assert_eq!(normalize_did(did), did);
+        assert_eq!(
+            normalize_did(&format!(
+                "{}#z6MkgYGF3thn8k1Fv4p4dWXKtsXCnLH7q9yw4QgNPULDmDKB",
+                did
+            )),
+            did
+        );
+        assert_eq!(normalize_did(&format!("{}/path?query", did)), did);
+    }
+}
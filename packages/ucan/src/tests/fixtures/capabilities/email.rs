@@ -36,7 +36,11 @@ pub enum EmailAction {
     Send,
 }
 
-impl Ability for EmailAction {}
+impl Ability for EmailAction {
+    fn enables(&self, other: &Self) -> bool {
+        self == other
+    }
+}
 
 impl ToString for EmailAction {
     fn to_string(&self) -> String {
@@ -11,7 +11,12 @@ pub enum WNFSCapLevel {
     SuperUser,
 }
 
-impl Ability for WNFSCapLevel {}
+impl Ability for WNFSCapLevel {
+    /// A level enables the levels below it.
+    fn enables(&self, other: &Self) -> bool {
+        self >= other
+    }
+}
 
 impl TryFrom<String> for WNFSCapLevel {
     type Error = anyhow::Error;
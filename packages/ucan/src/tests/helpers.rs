@@ -1,6 +1,7 @@
 use super::fixtures::{EmailSemantics, Identities};
 use crate::{builder::UcanBuilder, capability::CapabilitySemantics};
 use anyhow::Result;
+use base64::Engine;
 use did_key::PatchedKeyPair;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_ipld_dagcbor::{from_slice, to_vec};
@@ -12,6 +13,15 @@ where
     Ok(from_slice(&to_vec(data)?)?)
 }
 
+/// Decodes the header (0) or the payload (1) of a JWT.
+pub fn jwt_part(token: &str, index: usize) -> String {
+    let part = token.split('.').nth(index).unwrap();
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(part)
+        .unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
 pub async fn scaffold_ucan_builder(identities: &Identities) -> Result<UcanBuilder<PatchedKeyPair>> {
     let email_semantics = EmailSemantics {};
     let send_email_as_bob = email_semantics
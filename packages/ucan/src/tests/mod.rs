@@ -5,4 +5,5 @@ mod chain;
 mod crypto;
 pub mod fixtures;
 pub mod helpers;
+mod store;
 mod ucan;
@@ -0,0 +1,109 @@
+use super::fixtures::Identities;
+use crate::{
+    builder::UcanBuilder,
+    store::{LayeredStore, MemoryStore, UcanJwtStore},
+    time::now,
+    ucan::Ucan,
+};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+
+#[cfg(target_arch = "wasm32")]
+wasm_bindgen_test_configure!(run_in_browser);
+
+async fn ucan_expiring_at(identities: &Identities, expiration: u64) -> Ucan {
+    UcanBuilder::default()
+        .issued_by(&identities.alice_key)
+        .for_audience(identities.bob_did.as_str())
+        .with_expiration(expiration)
+        .with_nonce()
+        .build()
+        .unwrap()
+        .sign()
+        .await
+        .unwrap()
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_forgets_expired_ucans() {
+    let identities = Identities::new().await;
+    let mut store = MemoryStore::default();
+
+    let soon = ucan_expiring_at(&identities, now() + 10).await;
+    let later = ucan_expiring_at(&identities, now() + 60).await;
+    let soon_cid = store.write_ucan(&soon).unwrap();
+    let later_cid = store.write_ucan(&later).unwrap();
+    assert_eq!(
+        store.read_token(&soon_cid).await.unwrap(),
+        Some(soon.encode().unwrap())
+    );
+
+    store.prune_expired(now() + 30).unwrap();
+    assert!(store.read_token(&soon_cid).await.is_err());
+    assert_eq!(
+        store.read_token(&later_cid).await.unwrap(),
+        Some(later.encode().unwrap())
+    );
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_lists_ucans_by_expiration() {
+    let identities = Identities::new().await;
+    let mut store = MemoryStore::default();
+
+    let now = now();
+    let later_cid = store
+        .write_ucan(&ucan_expiring_at(&identities, now + 60).await)
+        .unwrap();
+    let soon_cid = store
+        .write_ucan(&ucan_expiring_at(&identities, now + 10).await)
+        .unwrap();
+    store
+        .write_ucan(&ucan_expiring_at(&identities, now + 3600).await)
+        .unwrap();
+    // not indexed without an expiration
+    store.write_token("token").await.unwrap();
+
+    assert_eq!(
+        store.expiring(now, now + 60).unwrap(),
+        vec![(soon_cid, now + 10), (later_cid, now + 60)]
+    );
+    assert_eq!(
+        store.expiring(now + 10, now + 300).unwrap(),
+        vec![(later_cid, now + 60)]
+    );
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_forgets_the_oldest_blocks_past_its_capacity() {
+    let mut store = MemoryStore::with_capacity(2);
+
+    let first = store.write_token("first").await.unwrap();
+    let second = store.write_token("second").await.unwrap();
+    // written again, it is not duplicated
+    store.write_token("first").await.unwrap();
+    let third = store.write_token("third").await.unwrap();
+
+    assert!(store.read_token(&first).await.is_err());
+    assert_eq!(store.read_token(&second).await.unwrap().unwrap(), "second");
+    assert_eq!(store.read_token(&third).await.unwrap().unwrap(), "third");
+    assert_eq!(store.blocks().unwrap().len(), 2);
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+#[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+pub async fn it_keeps_layered_writes_out_of_the_bottom_store() {
+    let mut bottom = MemoryStore::default();
+    let shared = bottom.write_token("shared").await.unwrap();
+
+    let mut store = LayeredStore::new(bottom.clone());
+    let own = store.write_token("own").await.unwrap();
+
+    assert_eq!(store.read_token(&shared).await.unwrap().unwrap(), "shared");
+    assert_eq!(store.read_token(&own).await.unwrap().unwrap(), "own");
+    assert!(bottom.read_token(&own).await.is_err());
+}
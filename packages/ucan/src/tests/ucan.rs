@@ -2,12 +2,20 @@ mod validate {
     use crate::{
         builder::UcanBuilder,
         capability::CapabilitySemantics,
-        crypto::did::DidParser,
-        tests::fixtures::{EmailSemantics, Identities, SUPPORTED_KEYS},
+        crypto::{did::DidParser, KeyMaterial, SignatureCache},
+        tests::{
+            fixtures::{EmailSemantics, Identities, SUPPORTED_KEYS},
+            helpers::jwt_part,
+        },
         time::now,
-        ucan::Ucan,
+        ucan::{Code, MultihashDigest, Ucan},
     };
     use anyhow::Result;
+    use base64::Engine;
+    use cid::Cid;
+    use did_key::PatchedKeyPair;
+    use libipld_core::raw::RawCodec;
+    use std::sync::Arc;
 
     use serde_json::json;
     #[cfg(target_arch = "wasm32")]
@@ -38,6 +46,246 @@ mod validate {
         decoded_ucan.validate(None, &mut did_parser).await.unwrap();
     }
 
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_decodes_numbers_in_json_values() {
+        let identities = Identities::new().await;
+
+        let ucan = UcanBuilder::default()
+            .issued_by(&identities.alice_key)
+            .for_audience(identities.bob_did.as_str())
+            .with_lifetime(30)
+            .with_fact("limits", json!({ "depth": 2, "offset": -1, "ratio": 0.5 }))
+            .build()
+            .unwrap()
+            .sign()
+            .await
+            .unwrap();
+
+        let decoded_ucan = Ucan::try_from(ucan.encode().unwrap().as_str()).unwrap();
+        assert_eq!(decoded_ucan.facts(), ucan.facts());
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_decodes_bytes_and_links_in_json_values() {
+        let identities = Identities::new().await;
+        let link = Cid::new_v1(0x55, Code::Sha2_256.digest(b"hello")).to_string();
+
+        let ucan = UcanBuilder::default()
+            .issued_by(&identities.alice_key)
+            .for_audience(identities.bob_did.as_str())
+            .with_lifetime(30)
+            .with_fact(
+                "blob",
+                json!({ "data": { "/": { "bytes": "aGVsbG8" } }, "link": { "/": link } }),
+            )
+            .build()
+            .unwrap()
+            .sign()
+            .await
+            .unwrap();
+
+        // kept in their DAG-JSON form
+        let decoded_ucan = Ucan::try_from(ucan.encode().unwrap().as_str()).unwrap();
+        assert_eq!(decoded_ucan.facts(), ucan.facts());
+        let blob = &decoded_ucan.facts().as_ref().unwrap()["blob"];
+        assert_eq!(blob["data"], json!({ "/": { "bytes": "aGVsbG8" } }));
+        assert_eq!(blob["link"], json!({ "/": link }));
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_decodes_jose_tokens_leniently() {
+        let identities = Identities::new().await;
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        let encode = |value: serde_json::Value| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+        };
+        let sign = |audience: serde_json::Value| {
+            let signed_data = format!(
+                "{}.{}",
+                encode(json!({ "alg": "EdDSA", "typ": "JWT" })),
+                encode(json!({
+                    "ucv": crate::ucan::UCAN_VERSION,
+                    "iss": identities.alice_did,
+                    "aud": audience,
+                    "exp": now() + 30,
+                    "cap": {},
+                    "jti": "request-1"
+                }))
+            );
+            let key = &identities.alice_key;
+            async move {
+                let signature = key.sign(signed_data.as_bytes()).await.unwrap();
+                format!(
+                    "{}.{}",
+                    signed_data,
+                    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+                )
+            }
+        };
+
+        let token = sign(json!([identities.bob_did])).await;
+        assert!(Ucan::try_from(token.as_str()).is_err());
+
+        let ucan = Ucan::from_str_lenient(&token).unwrap();
+        assert_eq!(ucan.audience(), identities.bob_did);
+        assert_eq!(ucan.extra_claims().get("jti"), Some(&json!("request-1")));
+        assert_eq!(ucan.encode().unwrap(), token);
+        let (canonical_token, _) = ucan.canonical_encode().unwrap();
+        assert!(jwt_part(&canonical_token, 1).contains(r#""jti":"request-1""#));
+        ucan.validate(None, &mut did_parser).await.unwrap();
+
+        let token = sign(json!([identities.bob_did, identities.mallory_did])).await;
+        assert!(Ucan::from_str_lenient(&token).is_err());
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_encodes_canonical_json() {
+        let identities = Identities::new().await;
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        let ucan = UcanBuilder::default()
+            .issued_by(&identities.alice_key)
+            .for_audience(identities.bob_did.as_str())
+            .with_lifetime(30)
+            .with_fact(
+                "b",
+                json!({
+                    "z": 1.5e-7,
+                    "a": 1e21,
+                    "\u{e9}": "\u{1}",
+                    // integers past 2^53 aren't rounded
+                    "n": 9007199254740993u64,
+                    "m": -9007199254740993i64,
+                }),
+            )
+            .with_canonical_json(true)
+            .build()
+            .unwrap()
+            .sign()
+            .await
+            .unwrap();
+
+        let (canonical_token, is_canonical) = ucan.canonical_encode().unwrap();
+        assert!(is_canonical);
+        assert!(jwt_part(&canonical_token, 1)
+            .contains(r#""fct":{"b":{"a":1e+21,"m":-9007199254740993,"n":9007199254740993,"z":1.5e-7,"é":"\u0001"}}"#));
+
+        let encoded_ucan = ucan.encode().unwrap();
+        assert_eq!(canonical_token, encoded_ucan);
+        let decoded_ucan = Ucan::try_from(encoded_ucan.as_str()).unwrap();
+        assert_eq!(decoded_ucan.encode().unwrap(), encoded_ucan);
+        decoded_ucan.validate(None, &mut did_parser).await.unwrap();
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_encodes_tokens_as_they_were_signed() {
+        let identities = Identities::new().await;
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        // valid JSON, but not DAG-JSON: unsorted keys and whitespace
+        let header = r#"{ "typ": "JWT", "alg": "EdDSA" }"#;
+        let payload = format!(
+            r#"{{ "iss": "{}", "aud": "{}", "ucv": "{}", "exp": {}, "cap": {{}} }}"#,
+            identities.alice_did,
+            identities.bob_did,
+            crate::ucan::UCAN_VERSION,
+            now() + 30
+        );
+        let signed_data = format!(
+            "{}.{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(header),
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = identities
+            .alice_key
+            .sign(signed_data.as_bytes())
+            .await
+            .unwrap();
+        let token = format!(
+            "{}.{}",
+            signed_data,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        );
+
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        ucan.validate(None, &mut did_parser).await.unwrap();
+        assert_eq!(ucan.encode().unwrap(), token);
+
+        let (canonical_token, is_canonical) = ucan.canonical_encode().unwrap();
+        assert!(!is_canonical);
+        assert_eq!(
+            jwt_part(&canonical_token, 0),
+            r#"{"alg":"EdDSA","typ":"JWT"}"#
+        );
+
+        // the CID is that of the token as it was signed, as computed by other
+        // implementations referencing it as a proof
+        let hasher = UcanBuilder::<PatchedKeyPair>::default_hasher();
+        assert_eq!(
+            ucan.to_cid(hasher).unwrap(),
+            Cid::new_v1(RawCodec.into(), hasher.digest(token.as_bytes()))
+        );
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
+    async fn it_caches_verified_signatures() {
+        let identities = Identities::new().await;
+        let cache = Arc::new(SignatureCache::new(1));
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS).with_signature_cache(cache.clone());
+
+        let ucan = UcanBuilder::default()
+            .issued_by(&identities.alice_key)
+            .for_audience(identities.bob_did.as_str())
+            .with_lifetime(30)
+            .build()
+            .unwrap()
+            .sign()
+            .await
+            .unwrap();
+
+        ucan.validate(None, &mut did_parser).await.unwrap();
+        assert_eq!(cache.len(), 1);
+        ucan.validate(None, &mut did_parser).await.unwrap();
+        assert_eq!(cache.len(), 1);
+
+        // the signature is a part of the cache key
+        let mut signature = ucan.signature().to_vec();
+        signature[0] ^= 1;
+        let token = ucan.encode().unwrap();
+        let forged_token = format!(
+            "{}.{}",
+            token.rsplit_once('.').unwrap().0,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+        );
+        let forged_ucan = Ucan::try_from(forged_token.as_str()).unwrap();
+        assert!(forged_ucan.validate(None, &mut did_parser).await.is_err());
+
+        // the least recently used entry is evicted
+        let other_ucan = UcanBuilder::default()
+            .issued_by(&identities.bob_key)
+            .for_audience(identities.alice_did.as_str())
+            .with_lifetime(30)
+            .build()
+            .unwrap()
+            .sign()
+            .await
+            .unwrap();
+        other_ucan.validate(None, &mut did_parser).await.unwrap();
+        assert_eq!(cache.len(), 1);
+        assert!(!cache.contains(&SignatureCache::key(
+            ucan.issuer(),
+            ucan.signed_data(),
+            ucan.signature()
+        )));
+    }
+
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
     #[cfg_attr(not(target_arch = "wasm32"), tokio::test)]
     async fn it_identifies_a_ucan_that_is_not_active_yet() {
@@ -54,7 +302,7 @@ mod validate {
             .await
             .unwrap();
 
-        assert!(ucan.is_too_early());
+        assert!(ucan.is_too_early(None));
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
@@ -72,7 +320,7 @@ mod validate {
             .await
             .unwrap();
 
-        assert!(!ucan.is_too_early());
+        assert!(!ucan.is_too_early(None));
     }
 
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
@@ -1,8 +1,22 @@
 use instant::SystemTime;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// The frozen time, 0 if not frozen.
+static FROZEN_TIME: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the current Unix time in seconds, or the frozen time.
 pub fn now() -> u64 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+    match FROZEN_TIME.load(Ordering::Relaxed) {
+        0 => SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs(),
+        time => time,
+    }
+}
+
+/// Freezes the time returned by [now] (for deterministic tests), or
+/// unfreezes it with `None`. The time is process-wide.
+pub fn set_time(time: Option<u64>) {
+    FROZEN_TIME.store(time.unwrap_or(0), Ordering::Relaxed);
 }
@@ -1,6 +1,6 @@
 use crate::{
     capability::Capabilities,
-    crypto::did::DidParser,
+    crypto::{did::DidParser, SignatureCache},
     serde::{Base64Encode, DagJson},
     time::now,
 };
@@ -21,6 +21,9 @@ pub type FactsMap = BTreeMap<String, Value>;
 pub struct UcanHeader {
     pub alg: String,
     pub typ: String,
+    /// Other header parameters (like "kid"), preserved as decoded.
+    #[serde(flatten)]
+    pub extra: FactsMap,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -38,6 +41,9 @@ pub struct UcanPayload {
     pub fct: Option<FactsMap>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prf: Option<Vec<String>>,
+    /// Unregistered claims, preserved as decoded.
+    #[serde(flatten)]
+    pub extra: FactsMap,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -63,6 +69,13 @@ impl Ucan {
         }
     }
 
+    /// Deserialize a token produced by other JOSE tooling: a single-element
+    /// "aud" array is normalized into a string. The signed data is kept as
+    /// decoded, so the signature still verifies.
+    pub fn from_str_lenient(ucan_token: &str) -> Result<Self> {
+        decode_token(ucan_token, true)
+    }
+
     /// Validate the UCAN's signature and timestamps
     pub async fn validate<'a>(
         &self,
@@ -73,7 +86,7 @@ impl Ucan {
             return Err(anyhow!("Expired"));
         }
 
-        if self.is_too_early() {
+        if self.is_too_early(now_time) {
             return Err(anyhow!("Not active yet (too early)"));
         }
 
@@ -83,18 +96,48 @@ impl Ucan {
     /// Validate that the signed data was signed by the stated issuer
     pub async fn check_signature<'a>(&self, did_parser: &mut DidParser) -> Result<()> {
         let key = did_parser.parse(&self.payload.iss)?;
-        key.verify(&self.signed_data, &self.signature).await
+        match did_parser.signature_cache() {
+            Some(cache) => {
+                let cache_key =
+                    SignatureCache::key(&self.payload.iss, &self.signed_data, &self.signature);
+                if cache.contains(&cache_key) {
+                    return Ok(());
+                }
+                key.verify(&self.signed_data, &self.signature).await?;
+                cache.insert(cache_key);
+                Ok(())
+            }
+            None => key.verify(&self.signed_data, &self.signature).await,
+        }
     }
 
     /// Produce a base64-encoded serialization of the UCAN suitable for
     /// transferring in a header field
     pub fn encode(&self) -> Result<String> {
-        let header = self.header.jwt_base64_encode()?;
-        let payload = self.payload.jwt_base64_encode()?;
+        // the header and payload are kept encoded as they were signed, as
+        // encoding them again may produce different JSON
+        let signed_data = std::str::from_utf8(&self.signed_data)?;
         let signature =
             base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice());
 
-        Ok(format!("{header}.{payload}.{signature}"))
+        Ok(format!("{signed_data}.{signature}"))
+    }
+
+    /// Encodes the UCAN with its header and payload as canonical JSON
+    /// (RFC 8785), as [crate::builder::UcanBuilder::with_canonical_json]
+    /// does, and returns whether it is the token as signed. If not, the
+    /// signature doesn't match the canonical token, which is then only
+    /// suitable to compare tokens by content.
+    pub fn canonical_encode(&self) -> Result<(String, bool)> {
+        let signed_data = format!(
+            "{}.{}",
+            self.header.jwt_base64_encode_canonical()?,
+            self.payload.jwt_base64_encode_canonical()?
+        );
+        let is_canonical = signed_data.as_bytes() == self.signed_data.as_slice();
+        let signature =
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(self.signature.as_slice());
+        Ok((format!("{signed_data}.{signature}"), is_canonical))
     }
 
     /// Returns true if the UCAN has past its expiration date
@@ -116,9 +159,9 @@ impl Ucan {
     }
 
     /// Returns true if the not-before ("nbf") time is still in the future
-    pub fn is_too_early(&self) -> bool {
+    pub fn is_too_early(&self, now_time: Option<u64>) -> bool {
         match self.payload.nbf {
-            Some(nbf) => nbf > now(),
+            Some(nbf) => nbf > now_time.unwrap_or_else(now),
             None => false,
         }
     }
@@ -149,6 +192,15 @@ impl Ucan {
         self.lifetime_begins_before(other) && self.lifetime_ends_after(other)
     }
 
+    /// Unregistered claims of the payload.
+    pub fn extra_claims(&self) -> &FactsMap {
+        &self.payload.extra
+    }
+
+    pub fn header(&self) -> &UcanHeader {
+        &self.header
+    }
+
     pub fn algorithm(&self) -> &str {
         &self.header.alg
     }
@@ -238,44 +290,66 @@ impl FromStr for Ucan {
     type Err = anyhow::Error;
 
     fn from_str(ucan_token: &str) -> Result<Self, Self::Err> {
-        // better to create multiple iterators than collect, or clone.
-        let signed_data = ucan_token
-            .split('.')
-            .take(2)
-            .map(String::from)
-            .reduce(|l, r| format!("{l}.{r}"))
-            .ok_or_else(|| anyhow!("Could not parse signed data from token string"))?;
-
-        let mut parts = ucan_token.split('.').map(|str| {
-            base64::engine::general_purpose::URL_SAFE_NO_PAD
-                .decode(str)
-                .map_err(|error| anyhow!(error))
-        });
-
-        let header = parts
-            .next()
-            .ok_or_else(|| anyhow!("Missing UCAN header in token part"))?
-            .map(|decoded| UcanHeader::from_dag_json(&decoded))
-            .map_err(|e| e.context("Could not decode UCAN header base64"))?
-            .map_err(|e| e.context("Could not parse UCAN header JSON"))?;
-
-        let payload = parts
-            .next()
-            .ok_or_else(|| anyhow!("Missing UCAN payload in token part"))?
-            .map(|decoded| UcanPayload::from_dag_json(&decoded))
-            .map_err(|e| e.context("Could not decode UCAN payload base64"))?
-            .map_err(|e| e.context("Could not parse UCAN payload JSON"))?;
-
-        let signature = parts
-            .next()
-            .ok_or_else(|| anyhow!("Missing UCAN signature in token part"))?
-            .map_err(|e| e.context("Could not parse UCAN signature base64"))?;
-
-        Ok(Ucan::new(
-            header,
-            payload,
-            signed_data.as_bytes().into(),
-            signature,
-        ))
+        decode_token(ucan_token, false)
+    }
+}
+
+fn decode_token(ucan_token: &str, lenient: bool) -> Result<Ucan> {
+    // better to create multiple iterators than collect, or clone.
+    let signed_data = ucan_token
+        .split('.')
+        .take(2)
+        .map(String::from)
+        .reduce(|l, r| format!("{l}.{r}"))
+        .ok_or_else(|| anyhow!("Could not parse signed data from token string"))?;
+
+    let mut parts = ucan_token.split('.').map(|str| {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(str)
+            .map_err(|error| anyhow!(error))
+    });
+
+    let header = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing UCAN header in token part"))?
+        .map(|decoded| UcanHeader::from_dag_json(&decoded))
+        .map_err(|e| e.context("Could not decode UCAN header base64"))?
+        .map_err(|e| e.context("Could not parse UCAN header JSON"))?;
+
+    let payload = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing UCAN payload in token part"))?
+        .map(|decoded| match lenient {
+            true => decode_lenient_payload(&decoded),
+            false => UcanPayload::from_dag_json(&decoded),
+        })
+        .map_err(|e| e.context("Could not decode UCAN payload base64"))?
+        .map_err(|e| e.context("Could not parse UCAN payload JSON"))?;
+
+    let signature = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing UCAN signature in token part"))?
+        .map_err(|e| e.context("Could not parse UCAN signature base64"))?;
+
+    Ok(Ucan::new(
+        header,
+        payload,
+        signed_data.as_bytes().into(),
+        signature,
+    ))
+}
+
+/// Decodes the payload, normalizing a single-element "aud" array.
+fn decode_lenient_payload(json_bytes: &[u8]) -> Result<UcanPayload> {
+    let mut payload = Value::from_dag_json(json_bytes)?;
+    if let Some(Value::Array(audiences)) = payload.get("aud") {
+        let [audience] = audiences.as_slice() else {
+            return Err(anyhow!(
+                "Expected a single audience, got {}",
+                audiences.len()
+            ));
+        };
+        payload["aud"] = audience.clone();
     }
+    Ok(serde_json::from_value(payload)?)
 }
@@ -0,0 +1,43 @@
+//! In a test binary of its own, as the frozen time is process-wide.
+
+use base64::Engine;
+use serde_json::json;
+use ucan::{
+    time::{now, set_time},
+    ucan::UCAN_VERSION,
+    Ucan,
+};
+
+#[test]
+fn it_freezes_the_time_of_the_process() {
+    let encode = |value: serde_json::Value| {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+    };
+    let token = format!(
+        "{}.{}.AA",
+        encode(json!({ "alg": "EdDSA", "typ": "JWT" })),
+        encode(json!({
+            "ucv": UCAN_VERSION,
+            "iss": "did:key:z6MkffDZCkCTWreg8868fG1FGFogcJj5X6PY93pPcWDn9bob",
+            "aud": "did:key:z6MkrZ1r5XBFZjBU34qyD8fueMbMRkKw17BZaq2ivKFjnz2z",
+            "nbf": 1_700_000_000,
+            "exp": 1_700_000_100,
+            "cap": {}
+        }))
+    );
+    let ucan = Ucan::try_from(token.as_str()).unwrap();
+
+    set_time(Some(1_700_000_050));
+    assert_eq!(now(), 1_700_000_050);
+    assert!(!ucan.is_too_early(None));
+    assert!(!ucan.is_expired(None));
+
+    set_time(Some(1_699_999_999));
+    assert!(ucan.is_too_early(None));
+
+    set_time(Some(1_700_000_101));
+    assert!(ucan.is_expired(None));
+
+    set_time(None);
+    assert!(now() > 1_700_000_101);
+}
@@ -0,0 +1,265 @@
+use crate::semantics::{with_semantics, AbilityAliases, SemanticsOptions};
+use anyhow::Result;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use ucan::capability::{
+    Ability, Capabilities, Capability, CapabilitySemantics, CapabilityView, Caveat, Scope,
+};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct AttenuationOptions {
+    #[serde(rename = "strictNamespaces")]
+    pub strict_namespaces: Option<bool>,
+    #[serde(rename = "superuserResource")]
+    pub superuser_resource: Option<String>,
+    pub semantics: Option<String>,
+    #[serde(rename = "abilityAliases")]
+    pub ability_aliases: Option<AbilityAliases>,
+    /// The DID issuing the parent capabilities, for the semantics resolving
+    /// resources by their issuer ("my:" of the ownership semantics).
+    pub issuer: Option<String>,
+}
+
+/// The result of a requested capability.
+#[derive(Debug, Serialize)]
+pub struct AttenuationItem {
+    pub resource: String,
+    pub ability: String,
+    pub caveat: serde_json::Value,
+    pub ok: bool,
+    /// Why the capability is rejected: "invalid" (not a capability of the
+    /// semantics), "resource", "ability" or "caveat" (not enabled by any of
+    /// the parent capabilities).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttenuationResponse {
+    /// The requested capabilities enabled by the parent capabilities.
+    pub capabilities: Capabilities,
+    pub items: Vec<AttenuationItem>,
+}
+
+pub fn compute_attenuation(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let parent: Handle<JsValue> = cx.argument(0)?;
+    let parent: Capabilities =
+        neon_serde2::from_value(&mut cx, parent).or_else(|e| cx.throw_error(e.to_string()))?;
+    let requested: Handle<JsValue> = cx.argument(1)?;
+    let requested: Capabilities =
+        neon_serde2::from_value(&mut cx, requested).or_else(|e| cx.throw_error(e.to_string()))?;
+    let options: AttenuationOptions = match cx.argument_opt(2) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => AttenuationOptions::default(),
+    };
+
+    let result = internal_compute_attenuation(&parent, &requested, &options)
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &result).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+/// Returns the largest subset of the requested capabilities enabled by the
+/// parent capabilities, with the result of each requested capability.
+pub fn internal_compute_attenuation(
+    parent: &Capabilities,
+    requested: &Capabilities,
+    options: &AttenuationOptions,
+) -> Result<AttenuationResponse> {
+    let semantics_options = SemanticsOptions {
+        strict_namespace: options.strict_namespaces.unwrap_or(false),
+        superuser_resource: options.superuser_resource.clone(),
+        ability_aliases: options.ability_aliases.clone().unwrap_or_default(),
+    };
+    let items = with_semantics!(
+        options.semantics.as_deref().unwrap_or("general"),
+        &semantics_options,
+        |semantics| attenuate(semantics, parent, requested, options.issuer.as_deref())
+    )?;
+
+    let capabilities = Capabilities::try_from(
+        items
+            .iter()
+            .filter(|item| item.ok)
+            .map(|item| {
+                Capability::new(
+                    item.resource.clone(),
+                    item.ability.clone(),
+                    item.caveat.clone(),
+                )
+            })
+            .collect::<Vec<Capability>>(),
+    )?;
+    Ok(AttenuationResponse {
+        capabilities,
+        items,
+    })
+}
+
+fn attenuate<Semantics, S, A>(
+    semantics: &Semantics,
+    parent: &Capabilities,
+    requested: &Capabilities,
+    issuer: Option<&str>,
+) -> Vec<AttenuationItem>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    let parent: Vec<CapabilityView<S, A>> = parent
+        .iter()
+        .filter_map(|capability| match issuer {
+            Some(issuer) => semantics.parse_issued_capability(&capability, issuer),
+            None => semantics.parse_capability(&capability),
+        })
+        .collect();
+
+    requested
+        .iter()
+        .map(|capability| {
+            let reason = match semantics.parse_capability(&capability) {
+                Some(view) => parent
+                    .iter()
+                    .map(|p| mismatch(p, &view))
+                    .min_by_key(|mismatch| match mismatch {
+                        None => 0,
+                        Some("caveat") => 1,
+                        Some("ability") => 2,
+                        Some(_) => 3,
+                    })
+                    .unwrap_or(Some("resource")),
+                None => Some("invalid"),
+            };
+            AttenuationItem {
+                resource: capability.resource,
+                ability: capability.ability,
+                caveat: capability.caveat,
+                ok: reason.is_none(),
+                reason: reason.map(str::to_owned),
+            }
+        })
+        .collect()
+}
+
+/// Returns the first part of the requested capability the parent capability
+/// doesn't enable, the same way as the verifier checks it.
+fn mismatch<S, A>(
+    parent: &CapabilityView<S, A>,
+    requested: &CapabilityView<S, A>,
+) -> Option<&'static str>
+where
+    S: Scope,
+    A: Ability,
+{
+    if !parent.resource().contains(requested.resource()) {
+        return Some("resource");
+    }
+    if !parent.ability().enables(requested.ability()) {
+        return Some("ability");
+    }
+    match (
+        Caveat::try_from(parent.caveat()),
+        Caveat::try_from(requested.caveat()),
+    ) {
+        (Ok(parent_caveat), Ok(caveat)) if parent_caveat.enables(&caveat) => None,
+        _ => Some("caveat"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_compute_attenuation() {
+        let parent = Capabilities::try_from(&json!({
+            "api:user/1": { "post/view": [{}], "post/edit": [{ "draft": true }] },
+        }))
+        .unwrap();
+        let requested = Capabilities::try_from(&json!({
+            "api:user/1/post/2": { "post/view": [{}], "post/edit": [{}, { "draft": true }] },
+            "api:user/2": { "post/view": [{}] },
+            "api:user/1/post/3": { "post/delete": [{}] },
+        }))
+        .unwrap();
+
+        let response =
+            internal_compute_attenuation(&parent, &requested, &AttenuationOptions::default())
+                .unwrap();
+        assert_eq!(
+            serde_json::to_value(&response.capabilities).unwrap(),
+            json!({
+                "api:user/1/post/2": { "post/view": [{}], "post/edit": [{ "draft": true }] },
+            })
+        );
+        let reasons: Vec<(&str, &str, Option<&str>)> = response
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.resource.as_str(),
+                    item.ability.as_str(),
+                    item.reason.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("api:user/1/post/2", "post/edit", Some("caveat")),
+                ("api:user/1/post/2", "post/edit", None),
+                ("api:user/1/post/2", "post/view", None),
+                ("api:user/1/post/3", "post/delete", Some("ability")),
+                ("api:user/2", "post/view", Some("resource")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compute_attenuation_issued() {
+        let parent = Capabilities::try_from(&json!({
+            "my:api": { "post/view": [{}] },
+        }))
+        .unwrap();
+        let requested = Capabilities::try_from(&json!({
+            "as:did:key:alice:api": { "post/view": [{}] },
+        }))
+        .unwrap();
+
+        let mut options = AttenuationOptions {
+            semantics: Some("ownership".to_string()),
+            ..Default::default()
+        };
+        let response = internal_compute_attenuation(&parent, &requested, &options).unwrap();
+        assert_eq!(response.items[0].reason.as_deref(), Some("resource"));
+
+        options.issuer = Some("did:key:alice".to_string());
+        let response = internal_compute_attenuation(&parent, &requested, &options).unwrap();
+        assert!(response.items[0].ok);
+    }
+
+    #[test]
+    fn test_superuser_resource_semantics() {
+        let capabilities = Capabilities::try_from(&json!({
+            "api:user/1": { "post/view": [{}] },
+        }))
+        .unwrap();
+        for semantics in ["ownership", "proof"] {
+            let options = AttenuationOptions {
+                semantics: Some(semantics.to_string()),
+                superuser_resource: Some("*:*".to_string()),
+                ..Default::default()
+            };
+            let error = internal_compute_attenuation(&capabilities, &capabilities, &options)
+                .unwrap_err()
+                .to_string();
+            assert_eq!(
+                error,
+                format!(r#"semantics "{semantics}" doesn't support superuserResource"#)
+            );
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use crate::namespace_functions::store_namespace;
+use crate::semantics::{with_semantics, SemanticsOptions};
+use crate::ucan_functions::{
+    get_key_material, resolve_proof_chain, verify_proof_chain, VerifyOptions, VerifyResponse,
+    SUPPORTED_KEYS,
+};
+use crate::{runtime, verification_limit};
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use did_key::VerificationMethod;
+use neon::prelude::*;
+use neon::types::buffer::TypedArray;
+use neon::types::{JsPromise, JsTypedArray};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read};
+use ucan::builder::{Signable, UcanBuilder};
+use ucan::capability::{Ability, Capability, CapabilitySemantics, Scope};
+use ucan::chain::{CapabilityInfo, ProofChain, ProofSource, ResolveOptions};
+use ucan::crypto::did::{normalize_did, DidParser};
+use ucan::crypto::KeyMaterial;
+use ucan::ucan::{Code, MultihashDigest};
+use ucan::Ucan;
+
+/// A self-contained bundle: the leaf token and all its proofs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Bundle {
+    pub token: String,
+    pub proofs: Option<Vec<String>>,
+}
+
+pub fn verify_bundle(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let bundle: Handle<JsValue> = cx.argument(0)?;
+    let bundle: Bundle = match bundle.downcast::<JsTypedArray<u8>, _>(&mut cx) {
+        Ok(car) => {
+            let car = car.as_slice(&cx).to_vec();
+            read_car_bundle(&car).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        Err(_) => {
+            neon_serde2::from_value(&mut cx, bundle).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+    };
+    let config: Handle<JsValue> = cx.argument(1)?;
+    let config: VerifyOptions =
+        neon_serde2::from_value(&mut cx, config).or_else(|e| cx.throw_error(e.to_string()))?;
+    let namespace = store_namespace(&mut cx, config.store_namespace.as_deref())?;
+
+    let mut did_parser =
+        DidParser::new(SUPPORTED_KEYS).with_signature_cache(namespace.signature_cache);
+
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = limit
+            .run(internal_verify_bundle(&bundle, config, &mut did_parser))
+            .await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, |mut cx| match result {
+            Ok(result) => match neon_serde2::to_value(&mut cx, &result) {
+                Ok(result) => Ok(result),
+                Err(e) => cx.throw_error(e.to_string()),
+            },
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+/// Verifies the bundle using only its tokens: "knownTokens" and
+/// "storeProofs" options are ignored.
+pub async fn internal_verify_bundle(
+    bundle: &Bundle,
+    mut config: VerifyOptions,
+    did_parser: &mut DidParser,
+) -> Result<VerifyResponse> {
+    config.known_tokens = None;
+    config.store_proofs = None;
+    config.proof_store = None;
+    // the bundle may leave out proofs which aren't needed
+    let options = ResolveOptions {
+        lenient: config.lenient_decode.unwrap_or(false),
+        skip_missing: true,
+    };
+    let chain = resolve_proof_chain(
+        &bundle.token,
+        options,
+        &bundle.proofs,
+        config.at_time,
+        None,
+        did_parser,
+    )
+    .await?;
+    verify_proof_chain(&chain, &config, &mut Vec::new())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PruneOptions {
+    #[serde(flatten)]
+    pub verify: VerifyOptions,
+    /// The key of the token issuer, to re-issue the token with fewer proofs.
+    pub issuer: Option<VerificationMethod>,
+}
+
+pub fn prune_proof_chain(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let token = cx.argument::<JsString>(0)?.value(&mut cx);
+    let capabilities: Handle<JsValue> = cx.argument(1)?;
+    let capabilities: serde_json::Value = neon_serde2::from_value(&mut cx, capabilities)
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+    let options: Handle<JsValue> = cx.argument(2)?;
+    let mut options: serde_json::Value =
+        neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?;
+    match options.as_object_mut() {
+        Some(options) => options.insert("requiredCapabilities".to_owned(), capabilities),
+        None => return cx.throw_error("options must be an object"),
+    };
+    let mut options: PruneOptions =
+        serde_json::from_value(options).or_else(|e| cx.throw_error(e.to_string()))?;
+    let namespace = store_namespace(&mut cx, options.verify.store_namespace.as_deref())?;
+    if options.verify.store_proofs.unwrap_or(false) {
+        options.verify.proof_store = Some(namespace.store);
+    }
+
+    let mut did_parser =
+        DidParser::new(SUPPORTED_KEYS).with_signature_cache(namespace.signature_cache);
+
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = limit
+            .run(internal_prune_proof_chain(&token, options, &mut did_parser))
+            .await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, |mut cx| match result {
+            Ok(result) => match neon_serde2::to_value(&mut cx, &result) {
+                Ok(result) => Ok(result),
+                Err(e) => cx.throw_error(e.to_string()),
+            },
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+/// Returns a bundle of the token with only the proofs it needs: the least of
+/// the proofs read from the store, which [verify_bundle] can verify without
+/// the others. The proofs embedded into the token (as the "prf" fact) stay
+/// there. With the issuer key, the token is re-issued with the least of its
+/// direct proofs which satisfy the requirements, which are moved to the
+/// bundle. The re-issued token is a new token (with another CID), keeping the
+/// nonce, the header fields and the other claims.
+///
+/// Each proof is dropped in turn if the chain without it still verifies:
+/// chains are verified as resolved, and re-issued tokens before they are
+/// signed, so that only the final token is signed.
+pub async fn internal_prune_proof_chain(
+    token: &str,
+    options: PruneOptions,
+    did_parser: &mut DidParser,
+) -> Result<Bundle> {
+    let config = options.verify;
+    let resolve_options = ResolveOptions {
+        lenient: config.lenient_decode.unwrap_or(false),
+        ..Default::default()
+    };
+    let mut chain = resolve_proof_chain(
+        token,
+        resolve_options,
+        &config.known_tokens,
+        config.at_time,
+        config.proof_store.as_ref(),
+        did_parser,
+    )
+    .await?;
+    verify_proof_chain(&chain, &config, &mut Vec::new())?;
+    let verifies = |chain: &ProofChain| verify_proof_chain(chain, &config, &mut Vec::new()).is_ok();
+
+    if let Some(issuer) = &options.issuer {
+        let key_material = get_key_material(issuer)?;
+        if normalize_did(&key_material.get_did().await?) != normalize_did(chain.ucan().issuer()) {
+            return Err(anyhow!("the key is not of the token issuer"));
+        }
+
+        // drop each direct proof which isn't needed
+        let mut kept = chain.proofs().clone();
+        let mut index = 0;
+        while index < kept.len() {
+            let mut candidate = kept.clone();
+            candidate.remove(index);
+            let signable = reissue(&chain, &candidate, &config, &key_material)?;
+            let ucan = Ucan::new(
+                signable.ucan_header(),
+                signable.ucan_payload().await?,
+                Vec::new(),
+                Vec::new(),
+            );
+            if verifies(&ProofChain::from_resolved(ucan, candidate.clone())?) {
+                kept = candidate;
+            } else {
+                index += 1;
+            }
+        }
+        if kept.len() < chain.proofs().len() {
+            let ucan = reissue(&chain, &kept, &config, &key_material)?
+                .sign()
+                .await?;
+            chain = ProofChain::from_resolved(ucan, kept)?;
+        }
+    }
+
+    // then drop each proof read from the store which isn't needed
+    let mut cids = Vec::new();
+    collect_stored_cids(&chain, &mut cids)?;
+    for cid in cids.iter() {
+        let candidate = chain.without_stored_proof(cid);
+        if verifies(&candidate) {
+            chain = candidate;
+        }
+    }
+
+    let mut proofs = Vec::new();
+    collect_stored_proofs(&chain, &mut proofs)?;
+    Ok(Bundle {
+        token: match &options.issuer {
+            Some(_) => chain.ucan().encode()?,
+            None => token.to_owned(),
+        },
+        proofs: Some(proofs),
+    })
+}
+
+/// Re-issues the token of the chain with the given proofs, referring to them
+/// by CID. Capabilities which only the other proofs enable are dropped.
+fn reissue<'a, K: KeyMaterial>(
+    chain: &ProofChain,
+    proofs: &[ProofChain],
+    config: &VerifyOptions,
+    key_material: &'a K,
+) -> Result<Signable<'a, K>> {
+    let ucan = chain.ucan();
+    let options = SemanticsOptions {
+        strict_namespace: config.strict_namespaces.unwrap_or(false),
+        superuser_resource: config.superuser_resource.clone(),
+        ability_aliases: config.ability_aliases.clone().unwrap_or_default(),
+    };
+    let capabilities = with_semantics!(
+        config.semantics.as_deref().unwrap_or("general"),
+        &options,
+        |semantics| covered_capabilities(semantics, chain, proofs)
+    )?;
+    let facts: Vec<(String, serde_json::Value)> = ucan
+        .facts()
+        .iter()
+        .flatten()
+        .filter(|(name, _)| name.as_str() != "prf")
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    let mut builder = UcanBuilder::default()
+        .issued_by(key_material)
+        .for_audience(ucan.audience())
+        .claiming_capabilities(&capabilities)
+        .with_facts(&facts)
+        .with_type(&ucan.header().typ)
+        .with_canonical_json(ucan.canonical_encode()?.1);
+    if let Some(expiration) = ucan.expires_at() {
+        builder = builder.with_expiration(*expiration);
+    }
+    if let Some(not_before) = ucan.not_before() {
+        builder = builder.not_before(*not_before);
+    }
+    if let Some(nonce) = ucan.nonce() {
+        builder = builder.with_nonce_value(nonce);
+    }
+    for (key, value) in ucan.header().extra.iter() {
+        builder = builder.with_header_field(key, value)?;
+    }
+    for (key, value) in ucan.extra_claims().iter() {
+        builder = builder.with_extra_claim(key, value)?;
+    }
+
+    for proof in proofs {
+        let delegated = ucan.capabilities().iter().any(|capability| {
+            delegated_cid(&capability.resource).is_some_and(|cid| is_cid_of(proof.ucan(), &cid))
+        });
+        builder = match delegated {
+            true => builder.delegating_from(proof.ucan(), None)?,
+            false => builder.witnessed_by(proof.ucan(), None)?,
+        };
+    }
+    builder.build()
+}
+
+/// Returns the capabilities of the token of the chain which the given proofs
+/// enable, or which none of its proofs enables (originated by its issuer).
+/// "ucan:<cid>" capabilities are left out, to be added again for the kept
+/// proofs.
+fn covered_capabilities<Semantics, S, A>(
+    semantics: &Semantics,
+    chain: &ProofChain,
+    proofs: &[ProofChain],
+) -> Vec<Capability>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    let reduce = |proofs: &[ProofChain]| {
+        proofs
+            .iter()
+            .flat_map(|proof| proof.reduce_capabilities(semantics))
+            .collect::<Vec<CapabilityInfo<S, A>>>()
+    };
+    let kept = reduce(proofs);
+    let all = reduce(chain.proofs());
+    chain
+        .ucan()
+        .capabilities()
+        .iter()
+        .filter(|capability| delegated_cid(&capability.resource).is_none())
+        .filter(|capability| match semantics.parse_capability(capability) {
+            Some(capability) => {
+                kept.iter().any(|info| info.capability.enables(&capability))
+                    || !all.iter().any(|info| info.capability.enables(&capability))
+            }
+            // left for the verification to handle
+            None => true,
+        })
+        .collect()
+}
+
+/// Collects the CIDs of the proofs of the chain which aren't embedded into
+/// the tokens, parents first.
+fn collect_stored_cids(chain: &ProofChain, cids: &mut Vec<Cid>) -> Result<()> {
+    for proof in chain.proofs() {
+        if proof.source() == ProofSource::Store {
+            let cid = proof.ucan().to_cid(ProofChain::default_hasher())?;
+            if !cids.contains(&cid) {
+                cids.push(cid);
+            }
+        }
+    }
+    for proof in chain.proofs() {
+        collect_stored_cids(proof, cids)?;
+    }
+    Ok(())
+}
+
+/// Collects the proofs of the chain which aren't embedded into the tokens.
+fn collect_stored_proofs(chain: &ProofChain, proofs: &mut Vec<String>) -> Result<()> {
+    for proof in chain.proofs() {
+        if proof.source() == ProofSource::Store {
+            let token = proof.ucan().encode()?;
+            if !proofs.contains(&token) {
+                proofs.push(token);
+            }
+        }
+        collect_stored_proofs(proof, proofs)?;
+    }
+    Ok(())
+}
+
+/// Returns the CID of the proof delegated by the "ucan:<cid>" resource.
+fn delegated_cid(resource: &str) -> Option<Cid> {
+    Cid::try_from(resource.strip_prefix("ucan:")?).ok()
+}
+
+fn is_cid_of(ucan: &Ucan, cid: &Cid) -> bool {
+    Code::try_from(cid.hash().code())
+        .ok()
+        .and_then(|hasher| ucan.to_cid(hasher).ok())
+        .is_some_and(|ucan_cid| ucan_cid == *cid)
+}
+
+/// Reads a CARv1 bundle: the root is the leaf token, other blocks are proofs.
+pub fn read_car_bundle(car: &[u8]) -> Result<Bundle> {
+    let mut reader = Cursor::new(car);
+    let header_length = read_varint(&mut reader)?;
+    if header_length > car.len() as u64 {
+        return Err(anyhow!("invalid CAR: truncated header"));
+    }
+    let mut header = vec![0; header_length as usize];
+    reader.read_exact(&mut header)?;
+    let root = read_car_root(&header)?;
+
+    let mut bundle = Bundle::default();
+    let mut proofs = Vec::new();
+    while (reader.position() as usize) < car.len() {
+        let length = read_varint(&mut reader)? as usize;
+        let start = reader.position() as usize;
+        let cid = Cid::read_bytes(&mut reader)?;
+        let data_start = reader.position() as usize;
+        let end = start
+            .checked_add(length)
+            .filter(|end| *end <= car.len() && *end >= data_start)
+            .ok_or_else(|| anyhow!("invalid CAR: truncated block"))?;
+        let data = &car[data_start..end];
+        reader.set_position(end as u64);
+
+        let hasher = Code::try_from(cid.hash().code())
+            .map_err(|_| anyhow!("invalid CAR: unsupported hasher of block {}", cid))?;
+        if Cid::new_v1(cid.codec(), hasher.digest(data)) != cid {
+            return Err(anyhow!("invalid CAR: block doesn't match CID {}", cid));
+        }
+        let token = String::from_utf8(data.to_vec())?;
+        if cid == root {
+            bundle.token = token;
+        } else {
+            proofs.push(token);
+        }
+    }
+    if bundle.token.is_empty() {
+        return Err(anyhow!("invalid CAR: no root block {}", root));
+    }
+    bundle.proofs = Some(proofs);
+    Ok(bundle)
+}
+
+fn read_varint(reader: &mut Cursor<&[u8]>) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("invalid varint"))
+}
+
+/// Reads the single root of the DAG-CBOR header `{ roots: [CID], version: 1 }`.
+fn read_car_root(header: &[u8]) -> Result<Cid> {
+    let mut reader = Cursor::new(header);
+    let (major, entries) = read_cbor_head(&mut reader)?;
+    if major != 5 {
+        return Err(anyhow!("invalid CAR header"));
+    }
+    let mut roots = Vec::new();
+    let mut version = None;
+    for _ in 0..entries {
+        let key = read_cbor_bytes(&mut reader, 3)?;
+        match key.as_slice() {
+            b"version" => match read_cbor_head(&mut reader)? {
+                (0, value) => version = Some(value),
+                _ => return Err(anyhow!("invalid CAR header")),
+            },
+            b"roots" => {
+                let (major, count) = read_cbor_head(&mut reader)?;
+                if major != 4 {
+                    return Err(anyhow!("invalid CAR header"));
+                }
+                for _ in 0..count {
+                    // CIDs are tagged (42) byte strings, prefixed with 0x00
+                    if read_cbor_head(&mut reader)? != (6, 42) {
+                        return Err(anyhow!("invalid CAR header"));
+                    }
+                    let bytes = read_cbor_bytes(&mut reader, 2)?;
+                    match bytes.split_first() {
+                        Some((0, cid)) => roots.push(Cid::try_from(cid)?),
+                        _ => return Err(anyhow!("invalid CAR header")),
+                    }
+                }
+            }
+            _ => return Err(anyhow!("invalid CAR header")),
+        }
+    }
+    if version != Some(1) {
+        return Err(anyhow!("unsupported CAR version"));
+    }
+    match roots.as_slice() {
+        [root] => Ok(*root),
+        _ => Err(anyhow!("invalid CAR: expected a single root")),
+    }
+}
+
+/// Reads the major type and the argument of a CBOR data item.
+fn read_cbor_head(reader: &mut Cursor<&[u8]>) -> Result<(u8, u64)> {
+    let mut byte = [0u8];
+    reader.read_exact(&mut byte)?;
+    let major = byte[0] >> 5;
+    let argument = match byte[0] & 0x1f {
+        value @ 0..=23 => value as u64,
+        size @ 24..=27 => {
+            let mut bytes = [0u8; 8];
+            let length = 1 << (size - 24);
+            reader.read_exact(&mut bytes[8 - length..])?;
+            u64::from_be_bytes(bytes)
+        }
+        _ => return Err(anyhow!("unsupported CBOR item")),
+    };
+    Ok((major, argument))
+}
+
+/// Reads a CBOR byte (2) or text (3) string.
+fn read_cbor_bytes(reader: &mut Cursor<&[u8]>, major: u8) -> Result<Vec<u8>> {
+    let (item_major, length) = read_cbor_head(reader)?;
+    if item_major != major {
+        return Err(anyhow!("invalid CAR header"));
+    }
+    let remaining = reader.get_ref().len() as u64 - reader.position();
+    if length > remaining {
+        return Err(anyhow!("invalid CAR header"));
+    }
+    let mut bytes = vec![0; length as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use serde_json::json;
+    use ucan::Ucan;
+
+    fn write_varint(value: usize, out: &mut Vec<u8>) {
+        let mut value = value;
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn car(root: &str, tokens: &[&str]) -> Vec<u8> {
+        let cid = |token: &str| {
+            Ucan::try_from(token)
+                .unwrap()
+                .to_cid(Code::Blake3_256)
+                .unwrap()
+                .to_bytes()
+        };
+        let root = cid(root);
+        // { "roots": [CID], "version": 1 }
+        let mut header = vec![0xa2, 0x65];
+        header.extend(b"roots");
+        header.extend([0x81, 0xd8, 0x2a, 0x58, root.len() as u8 + 1, 0x00]);
+        header.extend(&root);
+        header.push(0x67);
+        header.extend(b"version");
+        header.push(0x01);
+
+        let mut out = Vec::new();
+        write_varint(header.len(), &mut out);
+        out.extend(header);
+        for token in tokens {
+            let cid = cid(token);
+            write_varint(cid.len() + token.len(), &mut out);
+            out.extend(cid);
+            out.extend(token.as_bytes());
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn test_verify_bundle() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let alice_token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.proofs = Some(vec![alice_token.clone()]);
+        config.add_proof_facts = Some(false);
+        let token = invoke(config).await.unwrap();
+
+        let config = || -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        let bundle = read_car_bundle(&car(&token, &[&alice_token, &token])).unwrap();
+        assert_eq!(bundle.token, token);
+        let response = internal_verify_bundle(&bundle, config(), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(response.cids.len(), 2);
+
+        // the bundle must be self-contained
+        let bundle: Bundle = serde_json::from_value(json!({ "token": token })).unwrap();
+        assert!(internal_verify_bundle(&bundle, config(), &mut did_parser)
+            .await
+            .is_err());
+
+        let mut tampered = car(&token, &[&alice_token, &token]);
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(read_car_bundle(&tampered).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prune_proof_chain() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let view = json!({ "api:user": { "user/view": [{}] } });
+        let edit = json!({ "api:doc": { "doc/edit": [{}] } });
+        let invoke =
+            |issuer, audience: &str, capabilities: &serde_json::Value, proofs: Vec<String>| {
+                let mut config = invoke_options(issuer, audience, capabilities);
+                config.proofs = Some(proofs);
+                config.add_proof_facts = Some(false);
+                config.add_nonce = Some(true);
+                config.header = serde_json::from_value(json!({ "kid": "key-1" })).unwrap();
+                config.canonical_json = Some(true);
+                invoke(config)
+            };
+        let view_token = invoke(&server_key, &alice_did, &view, vec![])
+            .await
+            .unwrap();
+        let edit_token = invoke(&server_key, &alice_did, &edit, vec![])
+            .await
+            .unwrap();
+        let unrelated_token = invoke(&server_key, &server_did, &edit, vec![])
+            .await
+            .unwrap();
+        let both = json!({ "api:user": { "user/view": [{}] }, "api:doc": { "doc/edit": [{}] } });
+        let token = invoke(
+            &alice_key,
+            &server_did,
+            &both,
+            vec![view_token.clone(), edit_token.clone()],
+        )
+        .await
+        .unwrap();
+
+        let options = |issuer: Option<&VerificationMethod>| -> PruneOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": view,
+                "knownTokens": [view_token, edit_token, unrelated_token],
+                "issuer": issuer,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        let config = || -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": view,
+            }))
+            .unwrap()
+        };
+
+        // unused known tokens, and proofs of the token which aren't needed,
+        // are left out of the bundle
+        let bundle = internal_prune_proof_chain(&token, options(None), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(bundle.token, token);
+        assert_eq!(bundle.proofs, Some(vec![view_token.clone()]));
+        internal_verify_bundle(&bundle, config(), &mut did_parser)
+            .await
+            .unwrap();
+
+        // the re-issued token needs only one proof
+        let bundle = internal_prune_proof_chain(&token, options(Some(&alice_key)), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(bundle.proofs, Some(vec![view_token.clone()]));
+        // a new token, claiming only what the kept proof enables
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        let reissued = Ucan::try_from(bundle.token.as_str()).unwrap();
+        assert_ne!(bundle.token, token);
+        assert_eq!(reissued.nonce(), ucan.nonce());
+        assert_eq!(reissued.header().extra.get("kid"), Some(&json!("key-1")));
+        assert!(reissued.canonical_encode().unwrap().1);
+        let capabilities: Vec<Capability> = reissued.capabilities().iter().collect();
+        assert_eq!(capabilities.len(), 1);
+        assert_eq!(capabilities[0].resource, "api:user");
+        internal_verify_bundle(&bundle, config(), &mut did_parser)
+            .await
+            .unwrap();
+
+        // only the issuer can re-issue
+        assert!(
+            internal_prune_proof_chain(&token, options(Some(&server_key)), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+}
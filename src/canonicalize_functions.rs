@@ -0,0 +1,21 @@
+use neon::prelude::*;
+use serde::Serialize;
+use ucan::Ucan;
+
+#[derive(Debug, Serialize)]
+pub struct CanonicalUcan {
+    pub token: String,
+    pub canonical: bool,
+}
+
+pub fn canonicalize_ucan(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let token: Handle<neon::types::JsValue> = cx.argument(0)?;
+    let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
+    let token: String = token.value(&mut cx);
+    let ucan = Ucan::try_from(token).or_else(|e| cx.throw_error(e.to_string()))?;
+    let (token, canonical) = ucan
+        .canonical_encode()
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+    let result = CanonicalUcan { token, canonical };
+    neon_serde2::to_value(&mut cx, &result).or_else(|e| cx.throw_error(e.to_string()))
+}
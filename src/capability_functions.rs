@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+use neon::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+use ucan::capability::{Capabilities, Capability};
+
+#[derive(Debug, Serialize)]
+pub struct ValidationError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ability: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caveat: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidationResponse {
+    pub ok: bool,
+    pub errors: Vec<ValidationError>,
+}
+
+pub fn build_capabilities(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let entries: Handle<JsValue> = cx.argument(0)?;
+    let entries: Value =
+        neon_serde2::from_value(&mut cx, entries).or_else(|e| cx.throw_error(e.to_string()))?;
+    let capabilities =
+        internal_build_capabilities(&entries).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &capabilities).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn validate_capabilities(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let capabilities: Handle<JsValue> = cx.argument(0)?;
+    let capabilities: Value = neon_serde2::from_value(&mut cx, capabilities)
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+    let result = internal_validate_capabilities(&capabilities);
+    neon_serde2::to_value(&mut cx, &result).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+/// Builds capabilities from `{ resource, ability, caveat? }` entries, the
+/// caveat is `{}` by default.
+pub fn internal_build_capabilities(entries: &Value) -> Result<Capabilities> {
+    let entries = entries
+        .as_array()
+        .ok_or_else(|| anyhow!("Entries must be an array."))?;
+    let mut capabilities = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let field = |name: &str| -> Result<String> {
+            match entry.get(name).and_then(|value| value.as_str()) {
+                Some(value) if !value.is_empty() => Ok(value.to_owned()),
+                _ => Err(anyhow!(
+                    "Entry #{} must have a non-empty string {:?}: {}",
+                    index,
+                    name,
+                    entry
+                )),
+            }
+        };
+        let resource = field("resource")?;
+        let ability = field("ability")?;
+        let caveat = match entry.get("caveat") {
+            None | Some(Value::Null) => Value::Object(Default::default()),
+            Some(caveat) if caveat.is_object() => caveat.clone(),
+            Some(caveat) => {
+                return Err(anyhow!(
+                    "Caveat of entry #{} must be an object: {}",
+                    index,
+                    caveat
+                ))
+            }
+        };
+        capabilities.push(Capability::new(resource, ability, caveat));
+    }
+    Capabilities::try_from(capabilities)
+}
+
+/// Reports all the errors of the capabilities shape.
+pub fn internal_validate_capabilities(capabilities: &Value) -> ValidationResponse {
+    let errors: Vec<ValidationError> = Capabilities::validate(capabilities)
+        .into_iter()
+        .map(|error| ValidationError {
+            resource: error.resource,
+            ability: error.ability,
+            caveat: error.caveat,
+            message: error.message,
+        })
+        .collect();
+    ValidationResponse {
+        ok: errors.is_empty(),
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_capabilities() {
+        let capabilities = internal_build_capabilities(&json!([
+            { "resource": "api:user/1", "ability": "post/view" },
+            { "resource": "api:user/1", "ability": "post/edit", "caveat": { "draft": true } },
+        ]))
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&capabilities).unwrap(),
+            json!({ "api:user/1": { "post/view": [{}], "post/edit": [{ "draft": true }] } })
+        );
+
+        let error = internal_build_capabilities(&json!([
+            { "resource": "api:user/1", "ability": "post/view" },
+            { "resource": "api:user/1", "ability": "post/edit", "caveat": [] },
+        ]))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Caveat of entry #1 must be an object: []"
+        );
+        assert!(internal_build_capabilities(&json!([{ "resource": "api:user/1" }])).is_err());
+    }
+
+    #[test]
+    fn test_validate_capabilities() {
+        let response = internal_validate_capabilities(&json!({
+            "api:user/1": { "post/view": [{}], "post/edit": [true] },
+        }));
+        assert!(!response.ok);
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].ability.as_deref(), Some("post/edit"));
+        assert_eq!(response.errors[0].caveat, Some(0));
+
+        assert!(internal_validate_capabilities(&json!({ "api:user/1": { "post/view": [{}] } })).ok);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::namespace_functions::store_namespace;
+use crate::provenance_functions::merge_fact_provenance;
+use crate::ucan_functions::{
+    merge_cids, merge_facts, proof_source_name, resolve_proof_chain, verify_proof_chain,
+    VerifyOptions, VerifyResponse, SUPPORTED_KEYS,
+};
+use crate::{runtime, verification_limit};
+use anyhow::{anyhow, Result};
+use neon::prelude::*;
+use neon::types::JsPromise;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use ucan::{
+    chain::{ProofChain, ResolveOptions},
+    crypto::did::DidParser,
+    ucan::Code,
+    ucan::FactsMap,
+    Ucan,
+};
+
+/// A resolved proof chain, kept in JS to evaluate requirements without
+/// parsing and verifying the tokens again.
+pub struct ProofChainHandle(ProofChain);
+
+impl Finalize for ProofChainHandle {}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ProofChainOptions {
+    #[serde(rename = "knownTokens")]
+    pub known_tokens: Option<Vec<String>>,
+    #[serde(rename = "atTime")]
+    pub at_time: Option<u64>,
+    #[serde(rename = "storeProofs")]
+    pub store_proofs: Option<bool>,
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+    #[serde(rename = "lenientDecode")]
+    pub lenient_decode: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainJson<'a> {
+    pub cid: String,
+    pub source: &'static str,
+    pub ucan: &'a Ucan,
+    pub proofs: Vec<ChainJson<'a>>,
+}
+
+impl<'a> ChainJson<'a> {
+    pub fn try_from_chain(chain: &'a ProofChain) -> Result<Self> {
+        Ok(ChainJson {
+            cid: chain.ucan().to_cid(Code::Blake3_256)?.to_string(),
+            source: proof_source_name(chain.source()),
+            ucan: chain.ucan(),
+            proofs: chain
+                .proofs()
+                .iter()
+                .map(ChainJson::try_from_chain)
+                .collect::<Result<Vec<ChainJson>>>()?,
+        })
+    }
+}
+
+pub fn build_proof_chain(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let token: Handle<neon::types::JsValue> = cx.argument(0)?;
+    let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
+    let token: String = token.value(&mut cx);
+    let options: ProofChainOptions = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => ProofChainOptions::default(),
+    };
+
+    let namespace = store_namespace(&mut cx, options.store_namespace.as_deref())?;
+    let proof_store = options
+        .store_proofs
+        .unwrap_or(false)
+        .then_some(namespace.store);
+
+    let mut did_parser =
+        DidParser::new(SUPPORTED_KEYS).with_signature_cache(namespace.signature_cache);
+
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = limit
+            .run(resolve_proof_chain(
+                &token,
+                ResolveOptions {
+                    lenient: options.lenient_decode.unwrap_or(false),
+                    ..Default::default()
+                },
+                &options.known_tokens,
+                options.at_time,
+                proof_store.as_ref(),
+                &mut did_parser,
+            ))
+            .await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, |mut cx| match result {
+            Ok(chain) => Ok(cx.boxed(ProofChainHandle(chain))),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+/// Checks the requirements against a resolved chain like
+/// [verify_proof_chain], validating the time bounds of every link again at
+/// "atTime" (default now): the chain may be reduced long after it was
+/// resolved.
+pub fn reduce_proof_chain(chain: &ProofChain, config: &VerifyOptions) -> Result<VerifyResponse> {
+    check_time_bounds(chain, config.at_time.unwrap_or_else(ucan::time::now))?;
+    verify_proof_chain(chain, config, &mut Vec::new())
+}
+
+fn check_time_bounds(chain: &ProofChain, at_time: u64) -> Result<()> {
+    if chain.ucan().is_expired(Some(at_time)) {
+        return Err(anyhow!("Expired"));
+    }
+    if chain.ucan().is_too_early(Some(at_time)) {
+        return Err(anyhow!("Not active yet (too early)"));
+    }
+    chain
+        .proofs()
+        .iter()
+        .try_for_each(|proof| check_time_bounds(proof, at_time))
+}
+
+pub fn proof_chain_reduce(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let chain = cx.argument::<JsBox<ProofChainHandle>>(0)?;
+    let config: Handle<neon::types::JsValue> = cx.argument(1)?;
+    let config: VerifyOptions =
+        neon_serde2::from_value(&mut cx, config).or_else(|e| cx.throw_error(e.to_string()))?;
+    let result =
+        reduce_proof_chain(&chain.0, &config).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &result).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn proof_chain_facts(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let chain = cx.argument::<JsBox<ProofChainHandle>>(0)?;
+    let mut facts = FactsMap::new();
+    merge_facts(&chain.0, &mut facts, None);
+    facts.remove("prf");
+    neon_serde2::to_value(&mut cx, &facts).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn proof_chain_fact_provenance(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let chain = cx.argument::<JsBox<ProofChainHandle>>(0)?;
+    let mut provenance = BTreeMap::new();
+    merge_fact_provenance(&chain.0, &mut provenance).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &provenance).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn proof_chain_cids(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let chain = cx.argument::<JsBox<ProofChainHandle>>(0)?;
+    let mut cids = Vec::new();
+    merge_cids(&chain.0, &mut cids).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &cids).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn proof_chain_json(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let chain = cx.argument::<JsBox<ProofChainHandle>>(0)?;
+    let json = ChainJson::try_from_chain(&chain.0).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &json).or_else(|e| cx.throw_error(e.to_string()))
+}
@@ -0,0 +1,36 @@
+use neon::prelude::*;
+use serde::Deserialize;
+use ucan::ucan::Code;
+use ucan::Ucan;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DecodeOptions {
+    pub lenient: Option<bool>,
+}
+
+pub fn decode_ucan(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let token: Handle<neon::types::JsValue> = cx.argument(0)?;
+    let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
+    let token: String = token.value(&mut cx);
+    let options: DecodeOptions = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => DecodeOptions::default(),
+    };
+    let ucan = match options.lenient.unwrap_or(false) {
+        true => Ucan::from_str_lenient(&token),
+        false => Ucan::try_from(token),
+    }
+    .or_else(|e| cx.throw_error(e.to_string()))?;
+    let result =
+        neon_serde2::to_value(&mut cx, &ucan).or_else(|e| cx.throw_error(e.to_string()))?;
+    let obj: Handle<JsObject> = result.downcast_or_throw(&mut cx)?;
+    let cid = ucan
+        .to_cid(Code::Blake3_256)
+        .or_else(|e| cx.throw_error(e.to_string()))?;
+    let cid = cx.string(cid.to_string());
+    obj.set(&mut cx, "cid", cid)?;
+
+    Ok(result)
+}
@@ -0,0 +1,531 @@
+use crate::semantics::{with_semantics, AbilityAliases, SemanticsOptions, SEMANTICS};
+use anyhow::{anyhow, Result};
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ucan::capability::{Ability, Capability, CapabilitySemantics, Scope, MAX_DEPTH_CAVEAT};
+
+/// The issuer of the ownership examples.
+const ROOT: &str = "did:example:root";
+/// Another issuer of the ownership examples.
+const ALICE: &str = "did:example:alice";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DescribeOptions {
+    pub semantics: Option<String>,
+    #[serde(rename = "strictNamespaces")]
+    pub strict_namespaces: Option<bool>,
+    #[serde(rename = "superuserResource")]
+    pub superuser_resource: Option<String>,
+    #[serde(rename = "abilityAliases")]
+    pub ability_aliases: Option<AbilityAliases>,
+}
+
+/// The rules of the semantics, with examples evaluated by the semantics.
+#[derive(Debug, Serialize)]
+pub struct SemanticsDescription {
+    pub name: String,
+    /// Names of the semantics compiled in.
+    pub available: Vec<&'static str>,
+    pub resource: RulesDescription,
+    pub ability: RulesDescription,
+    pub caveat: RulesDescription,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RulesDescription {
+    pub format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wildcard: Option<&'static str>,
+    pub rules: Vec<String>,
+    pub examples: Vec<ExampleResult>,
+}
+
+/// Whether the capability grants the required capability.
+#[derive(Debug, Serialize)]
+pub struct ExampleResult {
+    pub capability: ExampleCapability,
+    pub required: ExampleCapability,
+    /// False if either capability is invalid in the semantics.
+    pub valid: bool,
+    pub grants: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExampleCapability {
+    pub resource: String,
+    pub ability: String,
+    pub caveat: Value,
+    /// The issuer, for semantics where it matters (like ownership).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+}
+
+impl ExampleCapability {
+    fn new(resource: &str, ability: &str) -> Self {
+        ExampleCapability {
+            resource: resource.to_owned(),
+            ability: ability.to_owned(),
+            caveat: serde_json::json!({}),
+            issuer: None,
+        }
+    }
+
+    fn with_caveat(self, caveat: Value) -> Self {
+        ExampleCapability { caveat, ..self }
+    }
+
+    fn issued_by(self, issuer: &str) -> Self {
+        ExampleCapability {
+            issuer: Some(issuer.to_owned()),
+            ..self
+        }
+    }
+}
+
+/// The rules of a part of capabilities, with examples to evaluate.
+struct Rules {
+    format: String,
+    delimiter: Option<&'static str>,
+    wildcard: Option<&'static str>,
+    rules: Vec<String>,
+    examples: Vec<(ExampleCapability, ExampleCapability)>,
+}
+
+pub fn describe_semantics(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let options = match cx.argument_opt(0) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => DescribeOptions::default(),
+    };
+    let description =
+        internal_describe_semantics(&options).or_else(|e| cx.throw_error(e.to_string()))?;
+    neon_serde2::to_value(&mut cx, &description).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+pub fn internal_describe_semantics(options: &DescribeOptions) -> Result<SemanticsDescription> {
+    let name = options.semantics.as_deref().unwrap_or("general");
+    let semantics_options = SemanticsOptions {
+        strict_namespace: options.strict_namespaces.unwrap_or(false),
+        superuser_resource: options.superuser_resource.clone(),
+        ability_aliases: options.ability_aliases.clone().unwrap_or_default(),
+    };
+    let (resource, ability) = match name {
+        "general" => (
+            general_resource_rules(&semantics_options),
+            general_ability_rules(&semantics_options),
+        ),
+        "ownership" => (
+            ownership_resource_rules(),
+            general_ability_rules(&semantics_options),
+        ),
+        "proof" => proof_rules(),
+        #[cfg(feature = "email-semantics")]
+        "email" => email_rules(),
+        _ => (Rules::empty(), Rules::empty()),
+    };
+    with_semantics!(name, &semantics_options, |semantics| {
+        SemanticsDescription {
+            name: name.to_owned(),
+            available: SEMANTICS.to_vec(),
+            resource: evaluate(semantics, resource)?,
+            ability: evaluate(semantics, ability)?,
+            caveat: evaluate(semantics, caveat_rules(name))?,
+        }
+    })
+}
+
+impl Rules {
+    fn empty() -> Self {
+        Rules {
+            format: String::new(),
+            delimiter: None,
+            wildcard: None,
+            rules: Vec::new(),
+            examples: Vec::new(),
+        }
+    }
+}
+
+/// Evaluates the examples by the semantics, so that they can't go out of
+/// sync with the implementation.
+fn evaluate<Semantics, S, A>(semantics: &Semantics, rules: Rules) -> Result<RulesDescription>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    if rules.format.is_empty() {
+        return Err(anyhow!("the semantics has no description"));
+    }
+    let parse = |example: &ExampleCapability| {
+        let capability = Capability::new(
+            example.resource.clone(),
+            example.ability.clone(),
+            example.caveat.clone(),
+        );
+        match &example.issuer {
+            Some(issuer) => semantics.parse_issued_capability(&capability, issuer),
+            None => semantics.parse_capability(&capability),
+        }
+    };
+    let examples = rules
+        .examples
+        .into_iter()
+        .map(|(capability, required)| {
+            let (valid, grants) = match (parse(&capability), parse(&required)) {
+                (Some(capability), Some(required)) => (true, capability.enables(&required)),
+                _ => (false, false),
+            };
+            ExampleResult {
+                capability,
+                required,
+                valid,
+                grants,
+            }
+        })
+        .collect();
+    Ok(RulesDescription {
+        format: rules.format,
+        delimiter: rules.delimiter,
+        wildcard: rules.wildcard,
+        rules: rules.rules,
+        examples,
+    })
+}
+
+fn general_resource_rules(options: &SemanticsOptions) -> Rules {
+    let mut rules = vec![
+        "Schemes must be equal, then paths are compared part by part.".to_owned(),
+        r#"A path includes all its sub-paths: "user/1" includes "user/1/post/2"."#.to_owned(),
+        r#"The path "*" of a capability includes all paths of the scheme, while the required path "*" (all resources) is included only by "*"."#.to_owned(),
+        r#""*" as a part of a path matches any part: the required "user/*" means some user."#.to_owned(),
+    ];
+    let mut examples: Vec<(ExampleCapability, ExampleCapability)> = [
+        ("api:user", "api:user/1"),
+        ("api:user/1", "api:user"),
+        ("api:user/1", "api:user/1"),
+        ("api:user/1", "api:user/1/doc/1"),
+        ("api:user/1", "api:user/2"),
+        ("api:user/1", "api:doc/1"),
+        ("api:*", "api:user/1"),
+        ("api:user/1", "api:*"),
+        ("api:user/1", "api:user/*"),
+        ("api:user/*", "api:user/1"),
+        ("api:user/1/post/1", "api:user/*/post/2"),
+    ]
+    .into_iter()
+    .map(|(resource, required)| {
+        (
+            ExampleCapability::new(resource, "user/post"),
+            ExampleCapability::new(required, "user/post"),
+        )
+    })
+    .collect();
+    if let Some(superuser) = &options.superuser_resource {
+        rules.push(format!(
+            r#"The superuser resource "{}" includes resources of all schemes."#,
+            superuser
+        ));
+        examples.push((
+            ExampleCapability::new(superuser, "user/post"),
+            ExampleCapability::new("api:user/1", "user/post"),
+        ));
+    }
+    Rules {
+        format: "<scheme>:<path>".to_owned(),
+        delimiter: Some("/"),
+        wildcard: Some("*"),
+        rules,
+        examples,
+    }
+}
+
+fn general_ability_rules(options: &SemanticsOptions) -> Rules {
+    let mut rules = vec![
+        r#"An ability enables its sub-abilities: "user/post" enables "user/post/draft"."#
+            .to_owned(),
+        r#"The ability "*" enables all abilities, "*" at the end enables all sub-abilities ("user/*"), while a required "*" is enabled only by "*"."#.to_owned(),
+    ];
+    if options.strict_namespace {
+        rules.push(
+            r#"Namespaces must match exactly, and "*" is allowed only after a namespace ("user/*")."#
+                .to_owned(),
+        );
+    }
+    for (alias, canonical) in options.ability_aliases.iter() {
+        rules.push(format!(
+            r#"The ability "{}" (and its sub-abilities) is compared as "{}"."#,
+            alias, canonical
+        ));
+    }
+    let mut examples: Vec<(ExampleCapability, ExampleCapability)> = [
+        ("user/post", "user/post"),
+        ("user/post", "user/post/draft"),
+        ("user/post/draft", "user/post"),
+        ("*", "user/post"),
+        ("user/post", "*"),
+        ("user/*", "user/post"),
+        ("user/post", "user/*"),
+        ("msg/*", "file/send"),
+    ]
+    .into_iter()
+    .map(|(ability, required)| {
+        (
+            ExampleCapability::new("api:user", ability),
+            ExampleCapability::new("api:user", required),
+        )
+    })
+    .collect();
+    for (alias, canonical) in options.ability_aliases.iter() {
+        examples.push((
+            ExampleCapability::new("api:user", alias),
+            ExampleCapability::new("api:user", canonical),
+        ));
+    }
+    Rules {
+        format: "<namespace>/<ability>[/<sub-ability>]".to_owned(),
+        delimiter: Some("/"),
+        wildcard: Some("*"),
+        rules,
+        examples,
+    }
+}
+
+fn ownership_resource_rules() -> Rules {
+    let owned = format!("as:{ROOT}:api");
+    Rules {
+        format: "my:<scheme> | as:<did>:<scheme> | <scheme>:<path>".to_owned(),
+        delimiter: Some("/"),
+        wildcard: Some("*"),
+        rules: vec![
+            r#""my:<scheme>" means all resources of the scheme owned by the issuer of the token."#.to_owned(),
+            r#""as:<did>:<scheme>" means resources of the scheme owned by the DID, delegated by its "my:<scheme>"."#.to_owned(),
+            r#"The scheme "*" means all schemes."#.to_owned(),
+            "Other resources are compared as in the general semantics.".to_owned(),
+        ],
+        examples: vec![
+            (
+                ExampleCapability::new("my:api", "user/post").issued_by(ROOT),
+                ExampleCapability::new("api:user/1", "user/post"),
+            ),
+            (
+                ExampleCapability::new("my:api", "user/post").issued_by(ROOT),
+                ExampleCapability::new(&owned, "user/post"),
+            ),
+            (
+                ExampleCapability::new("my:*", "user/post").issued_by(ROOT),
+                ExampleCapability::new("docs:1", "user/post"),
+            ),
+            (
+                ExampleCapability::new("my:api", "user/post").issued_by(ALICE),
+                ExampleCapability::new(&owned, "user/post"),
+            ),
+            (
+                ExampleCapability::new(&owned, "user/post"),
+                ExampleCapability::new("api:user/1", "user/post"),
+            ),
+            (
+                ExampleCapability::new("api:user/1", "user/post"),
+                ExampleCapability::new("my:api", "user/post"),
+            ),
+        ],
+    }
+}
+
+fn proof_rules() -> (Rules, Rules) {
+    let resource = Rules {
+        format: "ucan:<cid> | ucan:./* | ucan:* | ucan://<did>/<scheme> | ucan://<did>/*"
+            .to_owned(),
+        delimiter: None,
+        wildcard: Some("*"),
+        rules: vec![
+            r#""ucan:*" includes all proofs, "ucan:./*" all proofs of the token, other selections only themselves."#.to_owned(),
+        ],
+        examples: [
+            ("ucan:*", "ucan:./*"),
+            ("ucan:./*", "ucan:*"),
+            ("ucan:./*", "ucan://did:example:root/*"),
+            ("ucan://did:example:root/*", "ucan://did:example:root/api"),
+        ]
+        .into_iter()
+        .map(|(resource, required)| {
+            (
+                ExampleCapability::new(resource, "ucan/*"),
+                ExampleCapability::new(required, "ucan/*"),
+            )
+        })
+        .collect(),
+    };
+    let ability = Rules {
+        format: "ucan/*".to_owned(),
+        delimiter: None,
+        wildcard: None,
+        rules: vec![r#"The only ability is "ucan/*", delegation of the proofs."#.to_owned()],
+        examples: vec![
+            (
+                ExampleCapability::new("ucan:*", "ucan/*"),
+                ExampleCapability::new("ucan:*", "ucan/*"),
+            ),
+            (
+                ExampleCapability::new("ucan:*", "ucan/*"),
+                ExampleCapability::new("ucan:*", "ucan/read"),
+            ),
+        ],
+    };
+    (resource, ability)
+}
+
+#[cfg(feature = "email-semantics")]
+fn email_rules() -> (Rules, Rules) {
+    let resource = Rules {
+        format: "mailto:<address>".to_owned(),
+        delimiter: None,
+        wildcard: None,
+        rules: vec!["Addresses must be equal.".to_owned()],
+        examples: vec![
+            (
+                ExampleCapability::new("mailto:alice@example.com", "email/send"),
+                ExampleCapability::new("mailto:alice@example.com", "email/send"),
+            ),
+            (
+                ExampleCapability::new("mailto:alice@example.com", "email/send"),
+                ExampleCapability::new("mailto:bob@example.com", "email/send"),
+            ),
+        ],
+    };
+    let ability = Rules {
+        format: "email/send".to_owned(),
+        delimiter: None,
+        wildcard: None,
+        rules: vec![r#"The only ability is "email/send"."#.to_owned()],
+        examples: Vec::new(),
+    };
+    (resource, ability)
+}
+
+/// Caveats are compared the same way by all semantics.
+fn caveat_rules(name: &str) -> Rules {
+    let (resource, ability) = match name {
+        "proof" => ("ucan:*", "ucan/*"),
+        "email" => ("mailto:alice@example.com", "email/send"),
+        _ => ("api:user", "user/post"),
+    };
+    let example = |caveat: Value, required: Value| {
+        (
+            ExampleCapability::new(resource, ability).with_caveat(caveat),
+            ExampleCapability::new(resource, ability).with_caveat(required),
+        )
+    };
+    Rules {
+        format: "{ <key>: <value>, ... }".to_owned(),
+        delimiter: None,
+        wildcard: None,
+        rules: vec![
+            "A caveat enables a required caveat which has all its keys with equal values, so {} enables any caveat.".to_owned(),
+            format!(
+                r#"The "{}" caveat limits re-delegation, and is not compared."#,
+                MAX_DEPTH_CAVEAT
+            ),
+        ],
+        examples: vec![
+            example(serde_json::json!({}), serde_json::json!({ "draft": true })),
+            example(
+                serde_json::json!({ "draft": true }),
+                serde_json::json!({ "draft": true, "topic": "foo" }),
+            ),
+            example(serde_json::json!({ "draft": true }), serde_json::json!({})),
+            example(
+                serde_json::json!({ "draft": true }),
+                serde_json::json!({ "draft": false }),
+            ),
+            example(
+                serde_json::json!({ MAX_DEPTH_CAVEAT: 1 }),
+                serde_json::json!({}),
+            ),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{internal_describe_semantics, DescribeOptions, ExampleResult};
+    use crate::semantics::{with_semantics, AbilityAliases, SemanticsOptions, SEMANTICS};
+
+    fn grants(examples: &[ExampleResult]) -> Vec<bool> {
+        examples.iter().map(|example| example.grants).collect()
+    }
+
+    #[test]
+    fn test_describe_semantics() {
+        let description = internal_describe_semantics(&DescribeOptions::default()).unwrap();
+        assert_eq!(description.name, "general");
+        // the tables of semantics.rs
+        assert_eq!(
+            grants(&description.resource.examples),
+            vec![true, false, true, true, false, false, true, false, true, true, false]
+        );
+        assert_eq!(
+            grants(&description.ability.examples),
+            vec![true, true, false, true, false, true, false, false]
+        );
+        assert_eq!(
+            grants(&description.caveat.examples),
+            vec![true, true, false, false, true]
+        );
+
+        let description = internal_describe_semantics(&DescribeOptions {
+            strict_namespaces: Some(true),
+            ability_aliases: Some(AbilityAliases::from([(
+                "msg/send".to_owned(),
+                "mail/send".to_owned(),
+            )])),
+            ..Default::default()
+        })
+        .unwrap();
+        let examples = &description.ability.examples;
+        assert!(!examples[3].valid);
+        assert!(examples.last().unwrap().grants);
+
+        let description = internal_describe_semantics(&DescribeOptions {
+            semantics: Some("ownership".to_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            grants(&description.resource.examples),
+            vec![true, true, true, false, true, false]
+        );
+
+        assert!(internal_describe_semantics(&DescribeOptions {
+            semantics: Some("unknown".to_owned()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_registered_semantics() {
+        for name in SEMANTICS {
+            assert!(
+                with_semantics!(*name, &SemanticsOptions::default(), |_semantics| ()).is_ok(),
+                "{} doesn't resolve",
+                name
+            );
+            let description = internal_describe_semantics(&DescribeOptions {
+                semantics: Some(name.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(description.name, *name);
+            assert!(
+                !description.resource.rules.is_empty() && !description.ability.rules.is_empty(),
+                "{} isn't described",
+                name
+            );
+        }
+    }
+}
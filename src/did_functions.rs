@@ -6,6 +6,7 @@ use did_key::{
     KeyFormat, P256KeyPair, PatchedKeyPair, Secp256k1KeyPair, VerificationMethod, X25519KeyPair,
 };
 use neon::prelude::*;
+use ucan::crypto::did::normalize_did;
 
 pub fn create_did(mut cx: FunctionContext) -> JsResult<JsValue> {
     let use_jose_format = cx.argument_opt(0);
@@ -53,7 +54,8 @@ pub fn resolve_did(mut cx: FunctionContext) -> JsResult<JsValue> {
     let did: Handle<JsValue> = cx.argument(0)?;
     let did: Handle<JsString> = did.downcast_or_throw(&mut cx)?;
     let did = did.value(&mut cx);
-    let key = did_key::resolve(&did).or_else(|e| cx.throw_error(format!("{:#?}", e)))?;
+    let key =
+        did_key::resolve(normalize_did(&did)).or_else(|e| cx.throw_error(format!("{:#?}", e)))?;
     let use_jose_format = cx.argument_opt(1);
     let use_jose_format = match use_jose_format {
         Some(use_jose_format) => {
@@ -221,7 +223,8 @@ pub fn simple_verify(mut cx: FunctionContext) -> JsResult<JsBoolean> {
         .decode(signature)
         .or_else(|e| cx.throw_error(format!("{:#?}", e)))?;
 
-    let key = did_key::resolve(&did).or_else(|e| cx.throw_error(format!("{:#?}", e)))?;
+    let key =
+        did_key::resolve(normalize_did(&did)).or_else(|e| cx.throw_error(format!("{:#?}", e)))?;
     if let Err(e) = key.verify(message.as_bytes(), &signature) {
         return cx.throw_error(format!("{:#?}", e));
     }
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use ucan::disclosure::{digest_disclosure, open_disclosure, DIGESTS_FACT};
+use ucan::ucan::FactsMap;
+use ucan::Ucan;
+
+/// A token issued with hidden facts, and disclosures of these facts.
+#[derive(Debug, Serialize)]
+pub struct DisclosedUcan {
+    pub token: String,
+    pub disclosures: BTreeMap<String, String>,
+}
+
+/// Merges facts of the links, the first ones first, with the hidden facts
+/// revealed by the disclosures. A disclosure only reveals a fact of the link
+/// holding its digest, so one link can't shadow the digest of another. Fails
+/// if a disclosure matches no digest.
+pub fn merge_disclosed_facts(links: &[&Ucan], disclosures: &[String]) -> Result<FactsMap> {
+    let mut disclosed = Vec::new();
+    for disclosure in disclosures.iter() {
+        let (name, value) = open_disclosure(disclosure)?;
+        disclosed.push((name, value, digest_disclosure(disclosure), false));
+    }
+
+    let mut facts = FactsMap::new();
+    for link in links.iter() {
+        let Some(link_facts) = link.facts() else {
+            continue;
+        };
+        for (name, value) in link_facts.iter() {
+            if name != DIGESTS_FACT && !facts.contains_key(name) {
+                facts.insert(name.clone(), value.clone());
+            }
+        }
+        let Some(digests) = link_facts
+            .get(DIGESTS_FACT)
+            .and_then(|digests| digests.as_object())
+        else {
+            continue;
+        };
+        for (name, value, digest, revealed) in disclosed.iter_mut() {
+            if digests.get(name.as_str()).and_then(|d| d.as_str()) == Some(digest.as_str()) {
+                *revealed = true;
+                if !facts.contains_key(name) {
+                    facts.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+
+    match disclosed.iter().find(|(_, _, _, revealed)| !revealed) {
+        Some((name, ..)) => Err(anyhow!(r#"invalid disclosure of fact "{}""#, name)),
+        None => Ok(facts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use crate::ucan_functions::{
+        build_disclosed_ucan, decode_proofs, get_key_material, internal_verify_ucan, VerifyOptions,
+        SUPPORTED_KEYS,
+    };
+    use serde_json::json;
+    use ucan::crypto::did::DidParser;
+    use ucan::Ucan;
+
+    #[tokio::test]
+    async fn test_hidden_facts() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let mut config = invoke_options(&server_key, &alice_did, &capabilities);
+        config.facts = serde_json::from_value(json!({ "email": "alice@example.com" })).unwrap();
+        config.hidden_facts = Some(vec!["email".to_owned()]);
+        let key_material = get_key_material(&config.issuer).unwrap();
+        let proofs = decode_proofs(&config).unwrap();
+        let alice_token = build_disclosed_ucan(config, proofs, key_material)
+            .await
+            .unwrap();
+        let alice_ucan = Ucan::try_from(alice_token.token.as_str()).unwrap();
+        assert!(alice_ucan.facts().as_ref().unwrap().get("email").is_none());
+
+        // alice tries to shadow the digest of the server
+        let alice_email =
+            ucan::disclosure::make_disclosure("email", &json!("mallory@example.com")).unwrap();
+        let alice_digest = ucan::disclosure::digest_disclosure(&alice_email);
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.facts = serde_json::from_value(json!({ "_sd": { "email": alice_digest } })).unwrap();
+        config.proofs = Some(vec![alice_token.token]);
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |disclosures: Vec<&String>| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "requiredFacts": { "email": "*" },
+                "disclosures": disclosures,
+            }))
+            .unwrap()
+        };
+        let email = alice_token.disclosures.get("email").unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let response = internal_verify_ucan(&token, verify_options(vec![email]), &mut did_parser)
+            .await
+            .unwrap();
+        let facts = response.facts.unwrap();
+        assert_eq!(facts.get("email"), Some(&json!("alice@example.com")));
+        assert!(!facts.contains_key("_sd"));
+
+        // not disclosed
+        assert!(
+            internal_verify_ucan(&token, verify_options(vec![]), &mut did_parser)
+                .await
+                .is_err()
+        );
+        // disclosed with another value
+        let forged = ucan::disclosure::make_disclosure("email", &json!("bob@example.com")).unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options(vec![&forged]), &mut did_parser)
+                .await
+                .is_err()
+        );
+        // a disclosure only reveals a fact of the link holding its digest
+        let mut config = verify_options(vec![&alice_email]);
+        config.facts_scope = Some("root".to_owned());
+        assert!(internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .is_err());
+        let mut config = verify_options(vec![email]);
+        config.facts_scope = Some("root".to_owned());
+        let response = internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .unwrap();
+        let facts = response.facts.unwrap();
+        assert_eq!(facts.get("email"), Some(&json!("alice@example.com")));
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Display;
+use ucan::capability::{Ability, CapabilitySemantics, Scope};
+use url::Url;
+
+/// An email address resource: "mailto:<address>".
+#[derive(Clone, PartialEq, Debug)]
+pub struct EmailAddress(String);
+
+impl Scope for EmailAddress {
+    fn contains(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl TryFrom<Url> for EmailAddress {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Url) -> Result<Self> {
+        match value.scheme() {
+            "mailto" => Ok(EmailAddress(value.path().to_owned())),
+            _ => Err(anyhow!("Not an email address: {}", value)),
+        }
+    }
+}
+
+impl Display for EmailAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "mailto:{}", self.0)
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub enum EmailAction {
+    Send,
+}
+
+impl Ability for EmailAction {
+    fn enables(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl TryFrom<String> for EmailAction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        match value.as_str() {
+            "email/send" => Ok(EmailAction::Send),
+            _ => Err(anyhow!("Unsupported email action: {}", value)),
+        }
+    }
+}
+
+impl Display for EmailAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmailAction::Send => write!(f, "email/send"),
+        }
+    }
+}
+
+/// The email semantics: "mailto:<address>" resources with the "email/send"
+/// ability. Compiled in with the "email-semantics" feature.
+pub struct EmailSemantics {}
+
+impl CapabilitySemantics<EmailAddress, EmailAction> for EmailSemantics {}
+
+#[cfg(test)]
+mod tests {
+    use crate::attenuation::{internal_compute_attenuation, AttenuationOptions};
+    use serde_json::json;
+    use ucan::capability::Capabilities;
+
+    #[test]
+    fn test_email_semantics() {
+        let parent = Capabilities::try_from(&json!({
+            "mailto:alice@example.com": { "email/send": [{}] },
+        }))
+        .unwrap();
+        let requested = Capabilities::try_from(&json!({
+            "mailto:alice@example.com": { "email/send": [{}] },
+            "mailto:bob@example.com": { "email/send": [{}] },
+            "api:user": { "user/view": [{}] },
+        }))
+        .unwrap();
+        let options = AttenuationOptions {
+            semantics: Some("email".to_owned()),
+            ..Default::default()
+        };
+        let response = internal_compute_attenuation(&parent, &requested, &options).unwrap();
+        let reasons: Vec<Option<&str>> = response
+            .items
+            .iter()
+            .map(|item| item.reason.as_deref())
+            .collect();
+        assert_eq!(reasons, vec![Some("invalid"), None, Some("resource")]);
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use tinytemplate::TinyTemplate;
+use ucan::capability::Capability;
+use ucan::ucan::FactsMap;
+
+/// Max number of items of an array fact expanded in a required capability.
+pub const MAX_EXPANSION_ITEMS: usize = 100;
+
+/// Max number of requirements a required capability expands to.
+pub const MAX_EXPANSIONS: usize = 1_000;
+
+/// Expands array variables ("{name[*]}") of the required capability, returning
+/// a requirement for each combination of array items, with the items to
+/// render it with (as "{expansion_<index>}" variables).
+pub fn expand_requirement(
+    required_capability: &Capability,
+    facts: &FactsMap,
+) -> Result<Vec<(Capability, Vec<serde_json::Value>)>> {
+    let mut names = Vec::new();
+    array_variables(&required_capability.resource, &mut names);
+    array_variables(&required_capability.ability, &mut names);
+    if let Some(obj) = required_capability.caveat.as_object() {
+        for value in obj.values() {
+            if let Some(s) = value.as_str() {
+                array_variables(s, &mut names);
+            }
+        }
+    }
+
+    let mut expanded = vec![(required_capability.clone(), Vec::new())];
+    for (index, name) in names.iter().enumerate() {
+        let mut keys = name.split('.');
+        let items = keys
+            .next()
+            .and_then(|key| facts.get(key))
+            .and_then(|value| keys.try_fold(value, |value, key| value.get(key)))
+            .and_then(|value| value.as_array())
+            .filter(|items| !items.is_empty())
+            .ok_or_else(|| anyhow!(r#"invalid fact "{}""#, name))?;
+        if items.len() > MAX_EXPANSION_ITEMS {
+            return Err(anyhow!(
+                r#"too many items in fact "{}", max {}"#,
+                name,
+                MAX_EXPANSION_ITEMS
+            ));
+        }
+        if expanded.len() * items.len() > MAX_EXPANSIONS {
+            return Err(anyhow!(
+                r#"too many expansions of "{} {}", max {}"#,
+                required_capability.resource,
+                required_capability.ability,
+                MAX_EXPANSIONS
+            ));
+        }
+        let placeholder = format!("{{{}[*]}}", name);
+        let variable = format!("{{expansion_{}}}", index);
+        let replace = |s: &str| s.replace(&placeholder, &variable);
+        expanded = expanded
+            .into_iter()
+            .flat_map(|(capability, expanded_items)| {
+                items.iter().map(move |item| {
+                    let mut caveat = capability.caveat.clone();
+                    if let Some(obj) = caveat.as_object_mut() {
+                        for value in obj.values_mut() {
+                            if let Some(s) = value.as_str() {
+                                *value = serde_json::Value::String(replace(s));
+                            }
+                        }
+                    }
+                    let mut expanded_items = expanded_items.clone();
+                    expanded_items.push(item.clone());
+                    (
+                        Capability::new(
+                            replace(&capability.resource),
+                            replace(&capability.ability),
+                            caveat,
+                        ),
+                        expanded_items,
+                    )
+                })
+            })
+            .collect();
+    }
+    Ok(expanded)
+}
+
+/// Adds names of array variables ("{name[*]}") used in the template.
+fn array_variables(template: &str, names: &mut Vec<String>) {
+    let mut rest = template;
+    while let Some(end) = rest.find("[*]}") {
+        if let Some(start) = rest[..end].rfind('{') {
+            let name = &rest[start + 1..end];
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_owned());
+            }
+        }
+        rest = &rest[end + 4..];
+    }
+}
+
+/// Renders template variables of the required capability.
+pub fn render_requirement(
+    required_capability: &Capability,
+    context: &FactsMap,
+) -> Result<Capability> {
+    let mut tt = TinyTemplate::new();
+    tt.add_template("resource", &required_capability.resource)?;
+    let resource = tt.render("resource", context)?;
+    tt.add_template("ability", &required_capability.ability)?;
+    let ability = tt.render("ability", context)?;
+
+    let mut caveat = required_capability.caveat.clone();
+    if let Some(obj) = caveat.as_object_mut() {
+        let obj_copy = obj.clone();
+
+        for obj_item in obj_copy.iter() {
+            if let Some(s) = obj_item.1.as_str() {
+                if s.contains('{') {
+                    let mut tt = TinyTemplate::new();
+                    tt.add_template("caveat", s)?;
+                    let new_s = tt.render("caveat", context)?;
+                    if new_s != s {
+                        obj.insert(obj_item.0.to_owned(), serde_json::Value::String(new_s));
+                    }
+                }
+            }
+        }
+    }
+    Ok(Capability::new(resource, ability, caveat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use crate::ucan_functions::{
+        internal_check_ucan, internal_verify_ucan, VerifyOptions, SUPPORTED_KEYS,
+    };
+    use serde_json::json;
+    use ucan::crypto::did::DidParser;
+
+    #[tokio::test]
+    async fn test_array_expansion() {
+        let (server_did, server_key) = new_identity();
+
+        let mut config = invoke_options(
+            &server_key,
+            &server_did,
+            &json!({ "api:tenant/a": { "tenant/read": [{}] }, "api:tenant/b": { "tenant/read": [{}] } }),
+        );
+        config.facts = serde_json::from_value(json!({ "tenants": ["a", "b", "c"] })).unwrap();
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |resource: &str, expansion: &str| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": { resource: { "tenant/read": [{}] } },
+                "expansion": expansion,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let response = internal_check_ucan(
+            &token,
+            verify_options("api:tenant/{tenants[*]}", "all"),
+            &mut did_parser,
+        )
+        .await;
+        assert!(!response.ok);
+        assert_eq!(
+            response
+                .requirements
+                .iter()
+                .map(|r| (r.resource.as_str(), r.ok))
+                .collect::<Vec<(&str, bool)>>(),
+            vec![
+                ("api:tenant/a", true),
+                ("api:tenant/b", true),
+                ("api:tenant/c", false)
+            ]
+        );
+        internal_verify_ucan(
+            &token,
+            verify_options("api:tenant/{tenants[*]}", "any"),
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+        assert!(internal_verify_ucan(
+            &token,
+            verify_options("api:tenant/{users[*]}", "any"),
+            &mut did_parser
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_expansion_limits() {
+        let facts: FactsMap = serde_json::from_value(json!({
+            "many": (0..=MAX_EXPANSION_ITEMS).collect::<Vec<usize>>(),
+            "some": (0..50).collect::<Vec<usize>>(),
+            "other": (0..50).collect::<Vec<usize>>(),
+        }))
+        .unwrap();
+        let requirement = |resource: &str| {
+            Capability::new(resource.to_owned(), "tenant/read".to_owned(), json!({}))
+        };
+        let expanded = expand_requirement(&requirement("api:{some[*]}"), &facts).unwrap();
+        assert_eq!(expanded.len(), 50);
+        assert!(expand_requirement(&requirement("api:{many[*]}"), &facts).is_err());
+        // 2500 combinations
+        assert!(expand_requirement(&requirement("api:{some[*]}/{other[*]}"), &facts).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use crate::namespace_functions::store_namespace;
+use crate::{subscribe, unsubscribe};
+use anyhow::Result;
+use cid::Cid;
+use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use ucan::{
+    store::{MemoryStore, UcanJwtStore},
+    Ucan,
+};
+
+/// Default time in seconds before the expiration to notify at.
+const EXPIRING_WITHIN: u64 = 300;
+/// Default time in milliseconds between checks of the store.
+const EXPIRING_INTERVAL: u64 = 60_000;
+/// Max number of tokens notified by a check, the others being notified by
+/// the next checks.
+const MAX_EXPIRING_TOKENS: usize = 1_000;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ExpiringOptions {
+    #[serde(rename = "withinSeconds")]
+    pub within_seconds: Option<u64>,
+    pub interval: Option<u64>,
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+}
+
+/// A stored token which expires soon.
+#[derive(Debug, Serialize)]
+pub struct ExpiringToken {
+    pub cid: String,
+    pub token: String,
+    pub issuer: String,
+    pub audience: String,
+    pub expiration: u64,
+    #[serde(rename = "expiresIn")]
+    pub expires_in: u64,
+}
+
+pub fn on_token_expiring(mut cx: FunctionContext) -> JsResult<JsNumber> {
+    let callback = Arc::new(cx.argument::<JsFunction>(0)?.root(&mut cx));
+    let options = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => ExpiringOptions::default(),
+    };
+    let within = options.within_seconds.unwrap_or(EXPIRING_WITHIN);
+    let interval = Duration::from_millis(options.interval.unwrap_or(EXPIRING_INTERVAL).max(1));
+
+    let store = store_namespace(&mut cx, options.store_namespace.as_deref())?.store;
+    // the subscription doesn't keep the process alive
+    let mut channel = cx.channel();
+    channel.unref(&mut cx);
+
+    let id = subscribe(&mut cx, async move {
+        let mut notified = HashMap::new();
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let Ok(tokens) =
+                expiring_tokens(&store, within, MAX_EXPIRING_TOKENS, &mut notified).await
+            else {
+                continue;
+            };
+            for token in tokens {
+                let callback = callback.clone();
+                channel.send(move |mut cx| {
+                    let event = neon_serde2::to_value(&mut cx, &token)
+                        .or_else(|e| cx.throw_error(e.to_string()))?;
+                    callback
+                        .to_inner(&mut cx)
+                        .call_with(&cx)
+                        .arg(event)
+                        .exec(&mut cx)
+                });
+            }
+        }
+    })?;
+    Ok(cx.number(id as f64))
+}
+
+pub fn off_token_expiring(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let id = cx.argument::<JsNumber>(0)?.value(&mut cx);
+    let removed = unsubscribe(&mut cx, id as u64)?;
+    Ok(cx.boolean(removed))
+}
+
+/// Returns at most `limit` stored tokens expiring within the given seconds,
+/// soonest first, skipping already notified tokens. Notified tokens are
+/// remembered with their expiration until they expire.
+pub async fn expiring_tokens(
+    store: &MemoryStore,
+    within: u64,
+    limit: usize,
+    notified: &mut HashMap<Cid, u64>,
+) -> Result<Vec<ExpiringToken>> {
+    let now = ucan::time::now();
+    notified.retain(|_, expiration| *expiration > now);
+    let mut tokens = Vec::new();
+    for (cid, expiration) in store.expiring(now, now + within)? {
+        if tokens.len() >= limit {
+            break;
+        }
+        if notified.contains_key(&cid) {
+            continue;
+        }
+        // forgotten since listed
+        let Ok(Some(token)) = store.read_token(&cid).await else {
+            continue;
+        };
+        let Ok(ucan) = Ucan::try_from(token.as_str()) else {
+            continue;
+        };
+        notified.insert(cid, expiration);
+        tokens.push(ExpiringToken {
+            cid: cid.to_string(),
+            issuer: ucan.issuer().to_owned(),
+            audience: ucan.audience().to_owned(),
+            expiration,
+            expires_in: expiration - now,
+            token,
+        });
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_expiring_tokens() {
+        let (did, key) = new_identity();
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let mut store = MemoryStore::default();
+        let write = |expiration: u64| {
+            let mut config = invoke_options(&key, &did, &capabilities);
+            config.expiration = expiration;
+            let mut store = store.clone();
+            async move {
+                let token = invoke(config).await.unwrap();
+                store
+                    .write_ucan(&Ucan::try_from(token.as_str()).unwrap())
+                    .unwrap();
+                token
+            }
+        };
+        let now = ucan::time::now();
+        let later = write(now + 120).await;
+        let soon = write(now + 60).await;
+        write(now + 3600).await;
+
+        let mut notified = HashMap::new();
+        let tokens = expiring_tokens(&store, 300, 1, &mut notified)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, soon);
+        assert!(tokens[0].expires_in <= 60);
+        // the next check notifies the one left by the limit
+        let tokens = expiring_tokens(&store, 300, 1, &mut notified)
+            .await
+            .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token, later);
+        // notified once
+        assert!(expiring_tokens(&store, 300, 1, &mut notified)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // expired ones are forgotten
+        let expired = store.write_token("expired").await.unwrap();
+        notified.insert(expired, now - 1);
+        expiring_tokens(&store, 300, 1, &mut notified)
+            .await
+            .unwrap();
+        assert_eq!(notified.len(), 2);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::semantics::{with_semantics, SemanticsOptions};
+use crate::ucan_functions::{InvokeOptions, InvokeProofs, SUPPORTED_KEYS};
+use anyhow::{anyhow, Result};
+use ucan::capability::{Ability, Capability, CapabilitySemantics, Scope};
+use ucan::chain::{ParseFailureMode, ProofChain};
+use ucan::crypto::did::{normalize_did, DidParser};
+
+/// Checks the token lifetime is within the lifetime of every proof, as
+/// required for the proof chain to be valid. With `clamp`, narrows the token
+/// lifetime instead of failing.
+pub fn guard_proof_lifetime(
+    config: &mut InvokeOptions,
+    proofs: &InvokeProofs,
+    clamp: bool,
+) -> Result<()> {
+    let ucans = proofs
+        .proofs
+        .iter()
+        .flatten()
+        .chain(proofs.delegations.iter().flatten())
+        .chain(proofs.witnesses.iter().map(|(ucan, _)| ucan));
+    for ucan in ucans {
+        if let Some(expires_at) = *ucan.expires_at() {
+            if config.expiration > expires_at {
+                if !clamp {
+                    return Err(anyhow!("expiration is later than a proof expiration"));
+                }
+                config.expiration = expires_at;
+            }
+        }
+        if let Some(not_before) = *ucan.not_before() {
+            let problem = match config.not_before {
+                None => Some("notBefore is missing, while a proof has notBefore"),
+                Some(nbf) if nbf < not_before => {
+                    Some("notBefore is earlier than a proof notBefore")
+                }
+                Some(_) => None,
+            };
+            if let Some(problem) = problem {
+                if !clamp {
+                    return Err(anyhow!(problem));
+                }
+                config.not_before = Some(not_before);
+            }
+        }
+    }
+    if let Some(not_before) = config.not_before {
+        if not_before > config.expiration {
+            return Err(anyhow!("notBefore is after expiration"));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the token doesn't re-delegate capabilities of the proofs past
+/// their "maxDepth". A capability the proofs only allow to invoke may be
+/// claimed by an invocation, which is addressed to an originator of it.
+pub async fn guard_delegation_depth(config: &InvokeOptions, proofs: &InvokeProofs) -> Result<()> {
+    let Some(ucans) = &proofs.proofs else {
+        return Ok(());
+    };
+    let store = config.store.clone().unwrap_or_default();
+    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+    let mut chains = Vec::new();
+    for ucan in ucans.iter() {
+        chains.push(ProofChain::from_ucan(ucan.clone(), None, &mut did_parser, &store).await?);
+    }
+    let options = SemanticsOptions {
+        superuser_resource: config.superuser_resource.clone(),
+        ..Default::default()
+    };
+    let exhausted = with_semantics!(
+        config.semantics.as_deref().unwrap_or("general"),
+        &options,
+        |semantics| exhausted_capabilities(semantics, &chains, config)?
+    )?;
+    match exhausted.first() {
+        Some(capability) => Err(anyhow!(
+            r#"capability "{} {}" can't be re-delegated: maxDepth of a proof is exhausted"#,
+            capability.resource,
+            capability.ability
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the claimed capabilities which only proofs with no remaining
+/// depth enable, unless the audience is an originator of them.
+fn exhausted_capabilities<Semantics, S, A>(
+    semantics: &Semantics,
+    chains: &[ProofChain],
+    config: &InvokeOptions,
+) -> Result<Vec<Capability>>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    let mut infos = Vec::new();
+    for chain in chains.iter() {
+        infos.extend(chain.reduce_proof_capabilities_with(
+            semantics,
+            ParseFailureMode::Truncate,
+            &mut Vec::new(),
+        )?);
+    }
+    let audience = normalize_did(&config.audience);
+    Ok(config
+        .capabilities
+        .iter()
+        .filter(|capability| {
+            let Some(capability) = semantics.parse_capability(capability) else {
+                return false;
+            };
+            let mut enabling = infos
+                .iter()
+                .filter(|info| info.capability.enables(&capability))
+                .peekable();
+            enabling.peek().is_some()
+                && enabling.all(|info| {
+                    info.remaining_depth == Some(0)
+                        && !info
+                            .originators
+                            .iter()
+                            .any(|originator| normalize_did(originator) == audience)
+                })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use crate::ucan_functions::{internal_verify_ucan, VerifyOptions, SUPPORTED_KEYS};
+    use serde_json::json;
+    use ucan::crypto::did::DidParser;
+    use ucan::Ucan;
+
+    #[tokio::test]
+    async fn test_proof_lifetime() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let mut config = invoke_options(&server_key, &alice_did, &capabilities);
+        let now = ucan::time::now();
+        config.expiration = now + 60;
+        config.not_before = Some(now - 10);
+        let alice_token = invoke(config).await.unwrap();
+
+        let proof_options = |proof_lifetime: Option<&str>| {
+            let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+            config.expiration = now + 3600;
+            config.proofs = Some(vec![alice_token.clone()]);
+            config.proof_lifetime = proof_lifetime.map(str::to_owned);
+            config
+        };
+        let verify_options = || -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        // dead on arrival
+        let token = invoke(proof_options(None)).await.unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options(), &mut did_parser)
+                .await
+                .is_err()
+        );
+
+        assert_eq!(
+            invoke(proof_options(Some("error")))
+                .await
+                .unwrap_err()
+                .to_string(),
+            "expiration is later than a proof expiration"
+        );
+        let mut config = proof_options(Some("error"));
+        config.expiration = now + 60;
+        assert_eq!(
+            invoke(config).await.unwrap_err().to_string(),
+            "notBefore is missing, while a proof has notBefore"
+        );
+        let mut config = proof_options(Some("error"));
+        config.expiration = now + 60;
+        config.not_before = Some(now - 20);
+        assert_eq!(
+            invoke(config).await.unwrap_err().to_string(),
+            "notBefore is earlier than a proof notBefore"
+        );
+        assert!(invoke(proof_options(Some("unknown"))).await.is_err());
+
+        let token = invoke(proof_options(Some("clamp"))).await.unwrap();
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        assert_eq!(*ucan.expires_at(), Some(now + 60));
+        assert_eq!(*ucan.not_before(), Some(now - 10));
+        internal_verify_ucan(&token, verify_options(), &mut did_parser)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_depth() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let (bob_did, bob_key) = new_identity();
+        let (carol_did, carol_key) = new_identity();
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+
+        // alice may re-delegate once
+        let alice_token = invoke(invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "api:user": { "user/view": [{ "maxDepth": 1 }] } }),
+        ))
+        .await
+        .unwrap();
+        let delegate = |key, audience: &str, proof: &str| {
+            let mut config = invoke_options(key, audience, &capabilities);
+            config.proofs = Some(vec![proof.to_owned()]);
+            invoke(config)
+        };
+        let bob_token = delegate(&alice_key, &bob_did, &alice_token).await.unwrap();
+        let carol_token = delegate(&bob_key, &carol_did, &bob_token).await.unwrap();
+
+        let verify_options = || -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        for (key, proof) in [(&alice_key, &alice_token), (&bob_key, &bob_token)] {
+            let token = delegate(key, &server_did, proof).await.unwrap();
+            internal_verify_ucan(&token, verify_options(), &mut did_parser)
+                .await
+                .unwrap();
+        }
+        // in strict mode, bob can invoke the server but not delegate to carol
+        let mut config = invoke_options(&bob_key, &server_did, &capabilities);
+        config.proofs = Some(vec![bob_token.clone()]);
+        config.strict = Some(true);
+        invoke(config).await.unwrap();
+        let mut config = invoke_options(&bob_key, &carol_did, &capabilities);
+        config.proofs = Some(vec![bob_token.clone()]);
+        config.strict = Some(true);
+        assert!(invoke(config).await.is_err());
+
+        // the second re-delegation exceeds the depth
+        let token = delegate(&carol_key, &server_did, &carol_token)
+            .await
+            .unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options(), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::namespace_functions::{namespace_argument, store_namespace};
+use anyhow::{anyhow, Result};
+use neon::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Ledger of nonces of issued tokens, and of used tokens CIDs, for
+/// single-use tokens.
+#[derive(Debug, Default)]
+pub struct NonceLedger {
+    // CID -> (nonce, expiration)
+    issued: HashMap<String, (String, u64)>,
+    nonces: HashSet<String>,
+    // CID -> expiration
+    used: HashMap<String, u64>,
+}
+
+/// How long a used token is remembered when its expiration is unknown, in
+/// seconds.
+pub const USED_RETENTION: u64 = 24 * 60 * 60;
+
+impl NonceLedger {
+    /// Records an issued token, failing if its nonce or CID is already
+    /// recorded.
+    pub fn record(&mut self, cid: &str, nonce: &str, expiration: u64, now: u64) -> Result<()> {
+        self.check_nonce(nonce, now)?;
+        if self.issued.contains_key(cid) {
+            return Err(anyhow!(r#"duplicate token "{}""#, cid));
+        }
+        self.nonces.insert(nonce.to_owned());
+        self.issued
+            .insert(cid.to_owned(), (nonce.to_owned(), expiration));
+        Ok(())
+    }
+
+    /// Fails if the nonce is already recorded, to check it before signing a
+    /// token with it.
+    pub fn check_nonce(&mut self, nonce: &str, now: u64) -> Result<()> {
+        self.prune(now);
+        if self.nonces.contains(nonce) {
+            return Err(anyhow!(r#"duplicate nonce "{}""#, nonce));
+        }
+        Ok(())
+    }
+
+    /// Marks a token as used until its expiration: the given one, else the
+    /// one recorded when it was issued, else [USED_RETENTION] from now.
+    /// Returns false if it was already used.
+    pub fn mark_used(&mut self, cid: &str, expiration: Option<u64>, now: u64) -> bool {
+        self.prune(now);
+        if self.used.contains_key(cid) {
+            return false;
+        }
+        let expiration = expiration
+            .or_else(|| self.issued.get(cid).map(|(_, expiration)| *expiration))
+            .unwrap_or(now + USED_RETENTION);
+        self.used.insert(cid.to_owned(), expiration);
+        true
+    }
+
+    pub fn was_used(&self, cid: &str) -> bool {
+        self.used.contains_key(cid)
+    }
+
+    // expired tokens can't be used anyway
+    fn prune(&mut self, now: u64) {
+        self.used.retain(|_, expiration| *expiration >= now);
+        let nonces = &mut self.nonces;
+        self.issued.retain(|_, (nonce, expiration)| {
+            if *expiration < now {
+                nonces.remove(nonce);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// A nonce ledger shared between threads.
+pub type SharedLedger = Arc<Mutex<NonceLedger>>;
+
+pub fn lock_ledger(ledger: &Mutex<NonceLedger>) -> Result<MutexGuard<'_, NonceLedger>> {
+    ledger.lock().map_err(|_| anyhow!("poisoned mutex!"))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MarkUsedOptions {
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+    pub expiration: Option<u64>,
+}
+
+pub fn mark_used(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let cid: Handle<JsValue> = cx.argument(0)?;
+    let cid: Handle<JsString> = cid.downcast_or_throw(&mut cx)?;
+    let cid = cid.value(&mut cx);
+    let options: MarkUsedOptions = match cx.argument_opt(1) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => MarkUsedOptions::default(),
+    };
+    let ledger = store_namespace(&mut cx, options.store_namespace.as_deref())?.ledger;
+    let mut ledger = lock_ledger(&ledger).or_else(|e| cx.throw_error(e.to_string()))?;
+    Ok(cx.boolean(ledger.mark_used(&cid, options.expiration, ucan::time::now())))
+}
+
+pub fn was_used(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let cid: Handle<JsValue> = cx.argument(0)?;
+    let cid: Handle<JsString> = cid.downcast_or_throw(&mut cx)?;
+    let cid = cid.value(&mut cx);
+    let ledger = namespace_argument(&mut cx, 1)?.ledger;
+    let ledger = lock_ledger(&ledger).or_else(|e| cx.throw_error(e.to_string()))?;
+    Ok(cx.boolean(ledger.was_used(&cid)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NonceLedger, USED_RETENTION};
+
+    #[test]
+    fn test_nonce_ledger() {
+        let mut ledger = NonceLedger::default();
+        ledger.check_nonce("nonce1", 10).unwrap();
+        ledger.record("cid1", "nonce1", 100, 10).unwrap();
+        assert!(ledger.check_nonce("nonce1", 10).is_err());
+        assert!(ledger.record("cid2", "nonce1", 100, 10).is_err());
+        assert!(ledger.record("cid1", "nonce2", 100, 10).is_err());
+
+        // expired entries are pruned
+        ledger.record("cid2", "nonce2", 100, 101).unwrap();
+        ledger.record("cid1", "nonce1", 200, 101).unwrap();
+
+        assert!(!ledger.was_used("cid1"));
+        assert!(ledger.mark_used("cid1", None, 101));
+        assert!(!ledger.mark_used("cid1", None, 101));
+        assert!(ledger.was_used("cid1"));
+
+        // used tokens are pruned once expired: with the given expiration, the
+        // one recorded when issued, or after the retention period
+        assert!(ledger.mark_used("cid3", Some(150), 101));
+        assert!(ledger.mark_used("cid4", None, 101));
+        ledger.record("cid5", "nonce5", 300, 151).unwrap();
+        assert!(!ledger.was_used("cid3"));
+        assert!(ledger.was_used("cid1"));
+        assert!(ledger.mark_used("cid3", None, 201));
+        assert!(!ledger.was_used("cid1"));
+        assert!(ledger.was_used("cid4"));
+        ledger
+            .record("cid6", "nonce6", 300, 101 + USED_RETENTION + 1)
+            .unwrap();
+        assert!(!ledger.was_used("cid4"));
+    }
+}
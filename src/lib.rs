@@ -1,20 +1,344 @@
+use attenuation::*;
+use bundle_functions::*;
+use canonicalize_functions::*;
+use capability_functions::*;
+use chain_functions::*;
+use decode_functions::*;
+use describe_functions::*;
 use did_functions::*;
+use expiry_functions::*;
+use ledger::*;
+use namespace_functions::*;
 use neon::prelude::*;
-use once_cell::sync::OnceCell;
+use neon::thread::LocalKey;
+use self_test::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::AbortHandle;
+use ucan::crypto::SignatureCache;
+use ucan::store::MemoryStore;
 use ucan_functions::*;
 
+mod attenuation;
+mod bundle_functions;
+mod canonicalize_functions;
+mod capability_functions;
+mod chain_functions;
+mod decode_functions;
+mod describe_functions;
 mod did_functions;
+mod disclosure_functions;
+#[cfg(feature = "email-semantics")]
+mod email_semantics;
+mod expansion_functions;
+mod expiry_functions;
+mod guard_functions;
+mod ledger;
+mod lint_functions;
+mod namespace_functions;
+mod provenance_functions;
+mod self_test;
 mod semantics;
+#[cfg(test)]
+mod test_helpers;
 mod ucan_functions;
 
-// Lazily allocate a Tokio runtime to use as the thread pool.
-fn runtime<'a, C: Context<'a>>(cx: &mut C) -> NeonResult<&'static Runtime> {
-    static RUNTIME: OnceCell<Runtime> = OnceCell::new();
+/// Default time in milliseconds `shutdown()` waits for running tasks.
+const SHUTDOWN_TIMEOUT: u64 = 10_000;
+
+/// Data of an addon instance. Each worker thread loads its own instance, with
+/// its own stores, nonce ledgers and signature caches: nothing is shared with
+/// the instances of other worker threads.
+struct Instance {
+    runtime: Mutex<Option<InstanceRuntime>>,
+    shut_down: AtomicBool,
+    store: MemoryStore,
+    ledger: SharedLedger,
+    signature_cache: Arc<SignatureCache>,
+    namespaces: Mutex<HashMap<String, StoreNamespace>>,
+    subscriptions: Mutex<HashMap<u64, AbortHandle>>,
+    next_subscription: AtomicU64,
+    verification_limit: Mutex<VerificationLimit>,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Instance {
+            runtime: Default::default(),
+            shut_down: Default::default(),
+            store: new_proof_store(),
+            ledger: Default::default(),
+            signature_cache: new_signature_cache(),
+            namespaces: Default::default(),
+            subscriptions: Default::default(),
+            next_subscription: Default::default(),
+            verification_limit: Default::default(),
+        }
+    }
+}
+
+static INSTANCE: LocalKey<Instance> = LocalKey::new();
+
+/// The Tokio runtime of an addon instance, counting the tasks it runs.
+#[derive(Clone)]
+struct InstanceRuntime {
+    runtime: Arc<Runtime>,
+    tasks: Arc<AtomicUsize>,
+}
+
+/// Decrements the task count when the task completes (or is dropped).
+struct TaskGuard(Arc<AtomicUsize>);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl InstanceRuntime {
+    fn spawn<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.fetch_add(1, Ordering::SeqCst);
+        let guard = TaskGuard(self.tasks.clone());
+        self.runtime.spawn(async move {
+            let _guard = guard;
+            future.await
+        });
+    }
+
+    /// Spawns a task which runs until aborted, not awaited by `shutdown()`.
+    fn spawn_background<F>(&self, future: F) -> AbortHandle
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.runtime.spawn(future).abort_handle()
+    }
+
+    /// Waits for the running tasks at most `timeout`, then stops the runtime.
+    fn drain(self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while self.tasks.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if let Ok(runtime) = Arc::try_unwrap(self.runtime) {
+            runtime.shutdown_timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+    }
+}
+
+/// Bounds the number of concurrent verifications of the instance, unbounded
+/// by default.
+#[derive(Clone, Default)]
+struct VerificationLimit {
+    semaphore: Option<Arc<Semaphore>>,
+    /// Fail instead of waiting for a running verification to complete.
+    fail_fast: bool,
+}
+
+impl VerificationLimit {
+    /// Runs the verification once a permit is available.
+    async fn run<T, F>(&self, verification: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let _permit = self.acquire().await?;
+        verification.await
+    }
+
+    async fn acquire(&self) -> anyhow::Result<Option<OwnedSemaphorePermit>> {
+        let Some(semaphore) = &self.semaphore else {
+            return Ok(None);
+        };
+        let permit = match self.fail_fast {
+            true => semaphore
+                .clone()
+                .try_acquire_owned()
+                .map_err(|_| anyhow::anyhow!("too many concurrent verifications"))?,
+            false => semaphore.clone().acquire_owned().await?,
+        };
+        Ok(Some(permit))
+    }
+}
 
-    RUNTIME
-        .get_or_try_init(Runtime::new)
-        .or_else(|err| cx.throw_error(&err.to_string()))
+// Lazily allocate a Tokio runtime of the instance to use as the thread pool.
+fn runtime<'a, C: Context<'a>>(cx: &mut C) -> NeonResult<InstanceRuntime> {
+    let instance = INSTANCE.get_or_init_default(cx);
+    if instance.shut_down.load(Ordering::SeqCst) {
+        return cx.throw_error("the addon is shut down");
+    }
+    let mut instance_runtime = instance
+        .runtime
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?;
+    if let Some(runtime) = instance_runtime.as_ref() {
+        return Ok(runtime.clone());
+    }
+    let runtime = InstanceRuntime {
+        runtime: Arc::new(Runtime::new().or_else(|err| cx.throw_error(err.to_string()))?),
+        tasks: Arc::new(AtomicUsize::new(0)),
+    };
+    *instance_runtime = Some(runtime.clone());
+    Ok(runtime)
+}
+
+/// The limit of concurrent verifications of the instance.
+fn verification_limit<'a, C: Context<'a>>(cx: &mut C) -> NeonResult<VerificationLimit> {
+    let instance = INSTANCE.get_or_init_default(cx);
+    let limit = instance
+        .verification_limit
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?;
+    Ok(limit.clone())
+}
+
+/// Runs a background task until `unsubscribe()` or `shutdown()`, returning
+/// its subscription ID.
+fn subscribe<'a, C, F>(cx: &mut C, future: F) -> NeonResult<u64>
+where
+    C: Context<'a>,
+    F: Future<Output = ()> + Send + 'static,
+{
+    let runtime = runtime(cx)?;
+    let instance = INSTANCE.get_or_init_default(cx);
+    let id = instance.next_subscription.fetch_add(1, Ordering::SeqCst) + 1;
+    let handle = runtime.spawn_background(future);
+    instance
+        .subscriptions
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?
+        .insert(id, handle);
+    Ok(id)
+}
+
+/// Stops the subscription, returning false if there is no such one.
+fn unsubscribe<'a, C: Context<'a>>(cx: &mut C, id: u64) -> NeonResult<bool> {
+    let instance = INSTANCE.get_or_init_default(cx);
+    let handle = instance
+        .subscriptions
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?
+        .remove(&id);
+    Ok(match handle {
+        Some(handle) => {
+            handle.abort();
+            true
+        }
+        None => false,
+    })
+}
+
+/// Stops accepting work and resolves once the running tasks complete (or
+/// the timeout elapses), so that a worker thread can exit cleanly.
+fn shutdown(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let timeout = match cx.argument_opt(0) {
+        Some(options) if !options.is_a::<JsUndefined, _>(&mut cx) => {
+            let options: ShutdownOptions = neon_serde2::from_value(&mut cx, options)
+                .or_else(|e| cx.throw_error(e.to_string()))?;
+            options.timeout.unwrap_or(SHUTDOWN_TIMEOUT)
+        }
+        _ => SHUTDOWN_TIMEOUT,
+    };
+
+    let instance = INSTANCE.get_or_init_default(&mut cx);
+    instance.shut_down.store(true, Ordering::SeqCst);
+    for (_, handle) in instance
+        .subscriptions
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?
+        .drain()
+    {
+        handle.abort();
+    }
+    let runtime = instance
+        .runtime
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))?
+        .take();
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+
+    // The runtime can't be stopped on one of its own threads.
+    std::thread::spawn(move || {
+        if let Some(runtime) = runtime {
+            runtime.drain(Duration::from_millis(timeout));
+        }
+        deferred.settle_with(&channel, |mut cx| Ok(cx.undefined()));
+    });
+
+    Ok(promise)
+}
+
+/// Configures the runtime of the instance. Verifications already running or
+/// waiting keep the previous limit.
+fn configure_runtime(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let options: Handle<JsValue> = cx.argument(0)?;
+    let options: RuntimeOptions =
+        neon_serde2::from_value(&mut cx, options).or_else(|e| cx.throw_error(e.to_string()))?;
+    let semaphore = match options.max_concurrent_verifications {
+        Some(0) => return cx.throw_error("maxConcurrentVerifications must be positive"),
+        Some(max) => Some(Arc::new(Semaphore::new(max))),
+        None => None,
+    };
+    let instance = INSTANCE.get_or_init_default(&mut cx);
+    *instance
+        .verification_limit
+        .lock()
+        .or_else(|err| cx.throw_error(err.to_string()))? = VerificationLimit {
+        semaphore,
+        fail_fast: options.fail_fast.unwrap_or(false),
+    };
+    Ok(cx.undefined())
+}
+
+/// The environment variable enabling `setTime()`, which must be "1".
+const ALLOW_SET_TIME_ENV: &str = "NODE_UCAN_ALLOW_SET_TIME";
+
+/// Freezes the time (Unix time in seconds) of the process for tests, or
+/// unfreezes it with `null`. As it affects every verification of the
+/// process, it is disabled unless enabled by [ALLOW_SET_TIME_ENV].
+fn set_time(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    if std::env::var(ALLOW_SET_TIME_ENV).as_deref() != Ok("1") {
+        return cx.throw_error(format!(
+            "setTime() is for tests only, set {}=1 to enable it",
+            ALLOW_SET_TIME_ENV
+        ));
+    }
+    let time = match cx.argument_opt(0) {
+        Some(time) if !time.is_a::<JsUndefined, _>(&mut cx) && !time.is_a::<JsNull, _>(&mut cx) => {
+            let time = time
+                .downcast_or_throw::<JsNumber, _>(&mut cx)?
+                .value(&mut cx);
+            if !(time.is_finite() && time >= 1.0) {
+                return cx.throw_error("time must be a positive number of seconds");
+            }
+            Some(time as u64)
+        }
+        _ => None,
+    };
+    ucan::time::set_time(time);
+    Ok(cx.undefined())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ShutdownOptions {
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RuntimeOptions {
+    #[serde(rename = "maxConcurrentVerifications")]
+    max_concurrent_verifications: Option<usize>,
+    #[serde(rename = "failFast")]
+    fail_fast: Option<bool>,
 }
 
 #[neon::main]
@@ -25,14 +349,43 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("simpleSign", simple_sign)?;
     cx.export_function("simpleVerify", simple_verify)?;
     cx.export_function("invokeUcan", invoke_ucan)?;
+    cx.export_function("invokeUcanWithDisclosures", invoke_ucan_with_disclosures)?;
     cx.export_function("decodeUcan", decode_ucan)?;
+    cx.export_function("canonicalizeUcan", canonicalize_ucan)?;
+    cx.export_function("verifyUcanSignature", verify_ucan_signature)?;
     cx.export_function("verifyUcan", verify_ucan)?;
+    cx.export_function("checkUcan", check_ucan)?;
+    cx.export_function("verifyBundle", verify_bundle)?;
+    cx.export_function("pruneProofChain", prune_proof_chain)?;
+    cx.export_function("computeAttenuation", compute_attenuation)?;
+    cx.export_function("buildCapabilities", build_capabilities)?;
+    cx.export_function("describeSemantics", describe_semantics)?;
+    cx.export_function("validateCapabilities", validate_capabilities)?;
+    cx.export_function("buildProofChain", build_proof_chain)?;
+    cx.export_function("proofChainReduce", proof_chain_reduce)?;
+    cx.export_function("proofChainFacts", proof_chain_facts)?;
+    cx.export_function("proofChainFactProvenance", proof_chain_fact_provenance)?;
+    cx.export_function("proofChainCids", proof_chain_cids)?;
+    cx.export_function("proofChainJson", proof_chain_json)?;
+    cx.export_function("markUsed", mark_used)?;
+    cx.export_function("wasUsed", was_used)?;
+    cx.export_function("createStoreNamespace", create_store_namespace)?;
+    cx.export_function("dropStoreNamespace", drop_store_namespace)?;
+    cx.export_function("selfTest", self_test)?;
+    cx.export_function("onTokenExpiring", on_token_expiring)?;
+    cx.export_function("offTokenExpiring", off_token_expiring)?;
+    cx.export_function("configureRuntime", configure_runtime)?;
+    cx.export_function("shutdown", shutdown)?;
+    cx.export_function("setTime", set_time)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::VerificationLimit;
     use serde_json::json;
+    use std::sync::Arc;
+    use tokio::sync::Semaphore;
     use ucan::capability::Capabilities;
     use ucan::Ucan;
 
@@ -62,4 +415,37 @@ mod tests {
         let ucan = Ucan::try_from(token).unwrap();
         println!("ucan={:#?}", ucan);
     }
+
+    #[tokio::test]
+    async fn test_verification_limit() {
+        let limit = VerificationLimit {
+            semaphore: Some(Arc::new(Semaphore::new(1))),
+            fail_fast: true,
+        };
+        let permit = limit.acquire().await.unwrap();
+        let error = limit.run(async { Ok(()) }).await.unwrap_err();
+        assert_eq!(error.to_string(), "too many concurrent verifications");
+        drop(permit);
+        limit.run(async { Ok(()) }).await.unwrap();
+
+        // excess verifications wait for a permit
+        let limit = VerificationLimit {
+            fail_fast: false,
+            ..limit
+        };
+        let permit = limit.acquire().await.unwrap();
+        let waiting = tokio::spawn({
+            let limit = limit.clone();
+            async move { limit.run(async { Ok(()) }).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        drop(permit);
+        waiting.await.unwrap().unwrap();
+
+        VerificationLimit::default()
+            .run(async { Ok(()) })
+            .await
+            .unwrap();
+    }
 }
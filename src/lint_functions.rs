@@ -0,0 +1,130 @@
+use crate::semantics::{with_semantics, SemanticsOptions};
+use crate::ucan_functions::InvokeOptions;
+use ucan::capability::{Ability, Capabilities, Capability, CapabilitySemantics, Scope};
+use ucan::crypto::did::normalize_did;
+
+/// Max size of facts (serialized as JSON) allowed in strict mode.
+pub const MAX_FACTS_SIZE: usize = 64 * 1024;
+
+/// Checks the DID syntax "did:<method>:<method-specific-id>".
+pub fn is_valid_did(did: &str) -> bool {
+    let mut parts = normalize_did(did).splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("did"), Some(method), Some(id)) => {
+            !method.is_empty()
+                && method
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                && !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || ['.', '-', '_', ':', '%'].contains(&c))
+                && !id.ends_with(':')
+        }
+        _ => false,
+    }
+}
+
+/// Validates invocation options, returning all found problems.
+pub fn lint_invoke_options(config: &InvokeOptions) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if !is_valid_did(&config.audience) {
+        problems.push(format!(r#"invalid audience DID "{}""#, config.audience));
+    }
+
+    let options = SemanticsOptions {
+        superuser_resource: config.superuser_resource.clone(),
+        ..Default::default()
+    };
+    let invalid_capabilities = with_semantics!(
+        config.semantics.as_deref().unwrap_or("general"),
+        &options,
+        |semantics| invalid_capabilities(semantics, &config.capabilities)
+    )
+    .unwrap_or_else(|e| {
+        problems.push(e.to_string());
+        Vec::new()
+    });
+    for capability in invalid_capabilities {
+        problems.push(format!(
+            r#"invalid capability "{} {}""#,
+            capability.resource, capability.ability
+        ));
+    }
+
+    if config.expiration <= ucan::time::now() {
+        problems.push("expiration is in the past".to_owned());
+    }
+    if let Some(not_before) = config.not_before {
+        if not_before >= config.expiration {
+            problems.push("expiration is not after notBefore".to_owned());
+        }
+    }
+
+    if let Some(facts) = &config.facts {
+        match serde_json::to_vec(facts) {
+            Ok(bytes) => {
+                if bytes.len() > MAX_FACTS_SIZE {
+                    problems.push(format!(
+                        "facts size {} exceeds {} bytes",
+                        bytes.len(),
+                        MAX_FACTS_SIZE
+                    ));
+                }
+            }
+            Err(e) => problems.push(format!("invalid facts: {}", e)),
+        }
+    }
+
+    problems
+}
+
+fn invalid_capabilities<Semantics, S, A>(
+    semantics: &Semantics,
+    capabilities: &Capabilities,
+) -> Vec<Capability>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    capabilities
+        .iter()
+        .filter(|capability| semantics.parse_capability(capability).is_none())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{invoke_options, new_identity};
+    use serde_json::json;
+    use ucan::ucan::FactsMap;
+
+    #[test]
+    fn test_lint_invoke_options() {
+        let (_, issuer) = new_identity();
+        let mut config = invoke_options(
+            &issuer,
+            "did:key:z6MkmftLP2owSZPneufrtWF7t9j2HL7sXYVgoroM59sjD7yx",
+            &json!({ "api:app/1": { "book/view": [{}] } }),
+        );
+        assert!(lint_invoke_options(&config).is_empty());
+
+        config.audience = "alice".to_owned();
+        config.capabilities =
+            serde_json::from_value(json!({ "not a resource": { "book/view": [{}] } })).unwrap();
+        config.not_before = Some(config.expiration);
+        config.facts = Some(FactsMap::from([(
+            "a".to_owned(),
+            json!("x".repeat(MAX_FACTS_SIZE)),
+        )]));
+        assert_eq!(lint_invoke_options(&config).len(), 4);
+
+        config.expiration = 1;
+        config.not_before = None;
+        config.facts = None;
+        assert_eq!(lint_invoke_options(&config).len(), 3);
+    }
+}
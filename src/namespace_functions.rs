@@ -0,0 +1,127 @@
+use crate::ledger::SharedLedger;
+use crate::ucan_functions::{new_proof_store, new_signature_cache};
+use crate::{Instance, INSTANCE};
+use anyhow::{anyhow, Result};
+use neon::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
+use ucan::crypto::SignatureCache;
+use ucan::store::MemoryStore;
+
+/// The known tokens, nonce ledger and signature cache of a tenant, isolated
+/// from those of other store namespaces.
+#[derive(Clone)]
+pub struct StoreNamespace {
+    pub store: MemoryStore,
+    pub ledger: SharedLedger,
+    pub signature_cache: Arc<SignatureCache>,
+}
+
+impl StoreNamespace {
+    fn new() -> Self {
+        StoreNamespace {
+            store: new_proof_store(),
+            ledger: SharedLedger::default(),
+            signature_cache: new_signature_cache(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct NamespaceOptions {
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+}
+
+/// Returns the named store namespace of the instance, or without a name the
+/// default one: the store, ledger and signature cache of the instance.
+pub fn instance_namespace(instance: &Instance, name: Option<&str>) -> Result<StoreNamespace> {
+    let Some(name) = name else {
+        return Ok(StoreNamespace {
+            // clones share the same storage
+            store: instance.store.clone(),
+            ledger: instance.ledger.clone(),
+            signature_cache: instance.signature_cache.clone(),
+        });
+    };
+    instance
+        .namespaces
+        .lock()
+        .map_err(|_| anyhow!("poisoned mutex!"))?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow!(r#"unknown store namespace "{}""#, name))
+}
+
+/// Creates an empty store namespace in the instance. Returns false if it
+/// already exists.
+pub fn create_namespace(instance: &Instance, name: &str) -> Result<bool> {
+    let mut namespaces = instance
+        .namespaces
+        .lock()
+        .map_err(|_| anyhow!("poisoned mutex!"))?;
+    if namespaces.contains_key(name) {
+        return Ok(false);
+    }
+    namespaces.insert(name.to_owned(), StoreNamespace::new());
+    Ok(true)
+}
+
+/// Drops a store namespace from the instance. Returns false if there is no
+/// such one.
+pub fn drop_namespace(instance: &Instance, name: &str) -> Result<bool> {
+    Ok(instance
+        .namespaces
+        .lock()
+        .map_err(|_| anyhow!("poisoned mutex!"))?
+        .remove(name)
+        .is_some())
+}
+
+/// Returns the named store namespace of the instance of the worker thread,
+/// or without a name the default one.
+pub fn store_namespace<'a, C: Context<'a>>(
+    cx: &mut C,
+    name: Option<&str>,
+) -> NeonResult<StoreNamespace> {
+    let instance = INSTANCE.get_or_init_default(cx);
+    instance_namespace(instance, name).or_else(|e| cx.throw_error(e.to_string()))
+}
+
+/// Returns the store namespace named by the optional `{ storeNamespace }`
+/// argument at index `i`.
+pub fn namespace_argument(cx: &mut FunctionContext, i: usize) -> NeonResult<StoreNamespace> {
+    let options = match cx.argument_opt(i) {
+        Some(options) if !options.is_a::<JsUndefined, _>(cx) => {
+            neon_serde2::from_value(cx, options).or_else(|e| cx.throw_error(e.to_string()))?
+        }
+        _ => NamespaceOptions::default(),
+    };
+    store_namespace(cx, options.store_namespace.as_deref())
+}
+
+fn namespace_name(cx: &mut FunctionContext) -> NeonResult<String> {
+    let name = cx.argument::<JsString>(0)?.value(cx);
+    if name.is_empty() {
+        return cx.throw_error("store namespace name must not be empty");
+    }
+    Ok(name)
+}
+
+/// Creates an empty store namespace in the worker thread. Returns false if
+/// it already exists.
+pub fn create_store_namespace(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let name = namespace_name(&mut cx)?;
+    let instance = INSTANCE.get_or_init_default(&mut cx);
+    let created = create_namespace(instance, &name).or_else(|e| cx.throw_error(e.to_string()))?;
+    Ok(cx.boolean(created))
+}
+
+/// Drops a store namespace and its state. Returns false if there is no such
+/// one. Tasks already running keep their state until they complete.
+pub fn drop_store_namespace(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let name = namespace_name(&mut cx)?;
+    let instance = INSTANCE.get_or_init_default(&mut cx);
+    let dropped = drop_namespace(instance, &name).or_else(|e| cx.throw_error(e.to_string()))?;
+    Ok(cx.boolean(dropped))
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use ucan::chain::ProofChain;
+use ucan::ucan::Code;
+
+/// A fact asserted by a link of the chain.
+#[derive(Debug, Serialize)]
+pub struct FactAssertion {
+    pub cid: String,
+    pub issuer: String,
+    pub value: serde_json::Value,
+}
+
+/// Collects, for each fact of the chain, all the links asserting it (leaf
+/// first, as facts are merged), so conflicting values can be resolved by
+/// the application.
+pub fn merge_fact_provenance(
+    chain: &ProofChain,
+    provenance: &mut BTreeMap<String, Vec<FactAssertion>>,
+) -> Result<()> {
+    let cid = chain.ucan().to_cid(Code::Blake3_256)?.to_string();
+    for (name, value) in chain.ucan().facts().iter().flatten() {
+        if name == "prf" {
+            continue;
+        }
+        let assertions = provenance.entry(name.clone()).or_default();
+        if !assertions.iter().any(|assertion| assertion.cid == cid) {
+            assertions.push(FactAssertion {
+                cid: cid.clone(),
+                issuer: chain.ucan().issuer().to_owned(),
+                value: value.clone(),
+            });
+        }
+    }
+    for c in chain.proofs() {
+        merge_fact_provenance(c, provenance)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{invoke, invoke_options, new_identity};
+    use crate::ucan_functions::{resolve_proof_chain, SUPPORTED_KEYS};
+    use serde_json::json;
+    use ucan::crypto::did::DidParser;
+
+    #[tokio::test]
+    async fn test_fact_provenance() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+
+        let mut config = invoke_options(&server_key, &alice_did, &capabilities);
+        config.facts = serde_json::from_value(json!({ "tenant": "acme", "plan": "pro" })).unwrap();
+        let alice_token = invoke(config).await.unwrap();
+
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.facts = serde_json::from_value(json!({ "tenant": "other" })).unwrap();
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let chain = resolve_proof_chain(
+            &token,
+            Default::default(),
+            &None,
+            None,
+            None,
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+        let mut provenance = BTreeMap::new();
+        merge_fact_provenance(&chain, &mut provenance).unwrap();
+
+        let tenant: Vec<(&str, &serde_json::Value)> = provenance["tenant"]
+            .iter()
+            .map(|assertion| (assertion.issuer.as_str(), &assertion.value))
+            .collect();
+        assert_eq!(
+            tenant,
+            vec![
+                (alice_did.as_str(), &json!("other")),
+                (server_did.as_str(), &json!("acme")),
+            ]
+        );
+        assert_eq!(provenance["plan"].len(), 1);
+        assert!(!provenance.contains_key("prf"));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::runtime;
+use crate::ucan_functions::{
+    build_ucan, decode_proofs, get_key_material, internal_verify_ucan, InvokeOptions,
+    VerifyOptions, SUPPORTED_KEYS,
+};
+use anyhow::{anyhow, Result};
+use did_key::{generate, Config, CoreSign, DIDCore, Ed25519KeyPair, P256KeyPair, PatchedKeyPair};
+use neon::prelude::*;
+use neon::types::JsPromise;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use ucan::{crypto::did::DidParser, time::now, ucan::UCAN_VERSION};
+
+/// Key types which can sign UCANs.
+const SELF_TEST_KEY_TYPES: &[&str] = &["Ed25519", "P256"];
+
+#[derive(Debug, Serialize)]
+pub struct AlgorithmStatus {
+    pub algorithm: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestResponse {
+    pub ok: bool,
+    pub algorithms: Vec<AlgorithmStatus>,
+    pub versions: BTreeMap<String, String>,
+    #[serde(rename = "ucanVersion")]
+    pub ucan_version: String,
+}
+
+pub fn self_test(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = internal_self_test().await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, move |mut cx| {
+            match neon_serde2::to_value(&mut cx, &result) {
+                Ok(result) => Ok(result),
+                Err(e) => cx.throw_error(e.to_string()),
+            }
+        });
+    });
+
+    Ok(promise)
+}
+
+pub async fn internal_self_test() -> SelfTestResponse {
+    let mut algorithms = Vec::new();
+    for key_type in SELF_TEST_KEY_TYPES {
+        let result = test_key_type(key_type).await;
+        algorithms.push(AlgorithmStatus {
+            algorithm: key_type.to_string(),
+            ok: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+        });
+    }
+
+    SelfTestResponse {
+        ok: algorithms.iter().all(|status| status.ok),
+        algorithms,
+        versions: BTreeMap::from([
+            (
+                env!("CARGO_PKG_NAME").to_owned(),
+                env!("CARGO_PKG_VERSION").to_owned(),
+            ),
+            ("ucan".to_owned(), ucan::VERSION.to_owned()),
+            (
+                "ucan-key-support".to_owned(),
+                ucan_key_support::VERSION.to_owned(),
+            ),
+        ]),
+        ucan_version: UCAN_VERSION.to_owned(),
+    }
+}
+
+async fn test_key_type(key_type: &str) -> Result<()> {
+    let key: PatchedKeyPair = match key_type {
+        "Ed25519" => generate::<Ed25519KeyPair>(None),
+        "P256" => generate::<P256KeyPair>(None),
+        _ => return Err(anyhow!(r#"unsupported key type: "{}""#, key_type)),
+    };
+    let did_doc = key.get_did_document(Config {
+        use_jose_format: false,
+        serialize_secrets: true,
+    });
+    let did = did_doc.id;
+    let verification_method = did_doc
+        .verification_method
+        .first()
+        .ok_or_else(|| anyhow!("generate: no verification method"))?;
+
+    let message = b"self test";
+    let signature = key.sign(message);
+    did_key::resolve(&did)
+        .map_err(|e| anyhow!("resolve: {:?}", e))?
+        .verify(message, &signature)
+        .map_err(|e| anyhow!("verify: {:?}", e))?;
+
+    // the key delegates rights to itself, and invokes them
+    let capabilities = json!({ "test:self": { "test/run": [{}] } });
+    let invoke_options = |proofs: Vec<String>| -> Result<InvokeOptions> {
+        Ok(serde_json::from_value(json!({
+            "issuer": verification_method,
+            "audience": did,
+            "expiration": now() + 60,
+            "capabilities": capabilities,
+            "proofs": proofs,
+        }))?)
+    };
+    let mut proofs = vec![];
+    for _ in 0..2 {
+        let config = invoke_options(proofs)?;
+        let key_material = get_key_material(&config.issuer).map_err(|e| anyhow!("issue: {}", e))?;
+        let invoke_proofs = decode_proofs(&config)?;
+        let token = build_ucan(config, invoke_proofs, key_material)
+            .await
+            .map_err(|e| anyhow!("issue: {}", e))?;
+        proofs = vec![token];
+    }
+    let token = proofs.remove(0);
+
+    let config: VerifyOptions = serde_json::from_value(json!({
+        "rootIssuer": did,
+        "audience": did,
+        "requiredCapabilities": capabilities,
+    }))?;
+    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+    let response = internal_verify_ucan(&token, config, &mut did_parser)
+        .await
+        .map_err(|e| anyhow!("verify chain: {}", e))?;
+    if response.cids.len() != 2 {
+        return Err(anyhow!("verify chain: invalid chain length"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::internal_self_test;
+
+    #[tokio::test]
+    async fn test_self_test() {
+        let response = internal_self_test().await;
+        assert!(response.ok, "{:?}", response.algorithms);
+        assert_eq!(response.algorithms.len(), 2);
+        assert_eq!(response.versions.len(), 3);
+    }
+}
@@ -32,6 +32,10 @@
  *    | user/ *             | user/1            | Yes                          |
  *    | user/1/post/1       | user/ * /post/2   | No                           |
  *
+ *    Optionally, a superuser resource (like "*:*") can be configured, which
+ *    includes resources of all schemes. As any capability, it must be
+ *    originated by the root issuer to be accepted.
+ *
  * 2. Ability: "<namespace>/ability[/sub-ability]"
  *
  *    The ability is an action allowed for the resource.
@@ -51,93 +55,173 @@
  *    | user/post           | *                 | No      |
  *    | user/ *             | user/post         | Yes     |
  *    | user/post           | user/ *           | No      |
+ *    | msg/ *              | file/send         | No      |
+ *
+ *    With the strict namespace option, the namespace must match exactly, and
+ *    "*" is allowed only after a namespace ("user/ *"), so the ability "*"
+ *    is invalid and doesn't grant abilities across namespaces.
  *
  * 3. Caveats: "[{<key>: <value>}[, {}, ...]]"
  *
  * To be described...
+ *
+ * The ownership semantics additionally supports resources owned by a DID:
+ * "my:<scheme>" means all resources of the scheme owned by the issuer of a
+ * token, "as:<did>:<scheme>" means resources owned by the DID, delegated by
+ * its "my:<scheme>". The scheme "*" means all schemes.
+ *
+ *    | Capability resource (issuer) | Required resource  | Includes |
+ *    |------------------------------|--------------------|----------|
+ *    | my:api (root)                | api:user/1         | Yes      |
+ *    | my:api (root)                | as:<root>:api      | Yes      |
+ *    | my:* (root)                  | docs:1             | Yes      |
+ *    | my:api (alice)               | as:<root>:api      | No       |
+ *    | as:<root>:api                | api:user/1         | Yes      |
+ *    | api:user/1                   | my:api             | No       |
  */
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Display;
-use ucan::capability::{Ability, CapabilitySemantics, Scope};
+use ucan::capability::{
+    parse_resource, Ability, Capability, CapabilitySemantics, CapabilityView, Resource, Scope,
+};
 use url::Url;
 
 #[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub struct GeneralAbility {
     ability: String,
+    #[serde(skip)]
+    strict_namespace: bool,
 }
 
-impl Ability for GeneralAbility {}
-
-impl TryFrom<String> for GeneralAbility {
-    type Error = anyhow::Error;
-
-    fn try_from(value: String) -> Result<Self> {
-        Ok(GeneralAbility { ability: value })
+impl GeneralAbility {
+    /// The first part of the ability ("namespace/ability").
+    pub fn namespace(&self) -> &str {
+        self.ability.split('/').next().unwrap_or("")
     }
-}
 
-impl Display for GeneralAbility {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let content = &self.ability;
-        write!(f, "{content}")
-    }
-}
-
-impl PartialOrd for GeneralAbility {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    /// Makes the ability to require the exact namespace match. Returns None if
+    /// the ability has no namespace, or it is "*".
+    pub fn into_strict(self) -> Option<Self> {
+        let namespace = self.namespace();
+        if namespace.is_empty() || namespace == "*" || !self.ability.contains('/') {
+            return None;
+        }
+        Some(GeneralAbility {
+            strict_namespace: true,
+            ..self
+        })
     }
 }
 
-impl Ord for GeneralAbility {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+impl Ability for GeneralAbility {
+    fn enables(&self, other: &Self) -> bool {
         if self.ability == other.ability {
-            return std::cmp::Ordering::Equal;
+            return true;
+        }
+        if (self.strict_namespace || other.strict_namespace)
+            && self.namespace() != other.namespace()
+        {
+            return false;
         }
         if self.ability == "*" {
-            return std::cmp::Ordering::Greater;
+            return true;
         } else if other.ability == "*" {
-            return std::cmp::Ordering::Less;
+            return false;
         }
 
         let self_path_parts = self.ability.split('/');
         let mut other_path_parts = other.ability.split('/');
-        let mut result = std::cmp::Ordering::Equal;
+        let mut result = true;
 
         for part in self_path_parts {
-            match other_path_parts.nth(0) {
+            match other_path_parts.next() {
                 Some(other_part) => {
-                    if part == "*" && other_part == "*" {
-                        result = std::cmp::Ordering::Equal;
-                    } else if part == "*" {
-                        result = std::cmp::Ordering::Greater;
+                    if part == "*" {
+                        result = true;
                     } else if other_part == "*" {
-                        result = std::cmp::Ordering::Less;
+                        result = false;
                     } else if part != other_part {
-                        return std::cmp::Ordering::Less;
+                        return false;
                     }
                 }
-                None => return std::cmp::Ordering::Less,
+                None => return false,
             }
         }
 
-        if other_path_parts.next().is_some() {
-            std::cmp::Ordering::Greater
-        } else {
-            result
+        other_path_parts.next().is_some() || result
+    }
+}
+
+/// Ability aliases (like "msg/send"), each compared as its canonical ability
+/// (like "mail/send"), so that abilities can be renamed without invalidating
+/// delegations of the old names.
+pub type AbilityAliases = BTreeMap<String, String>;
+
+/// Replaces the longest alias which is the ability or its parent ability
+/// ("msg/send" of "msg/send/draft") with the canonical ability.
+fn resolve_alias(aliases: &AbilityAliases, ability: &str) -> String {
+    let mut prefix = ability;
+    loop {
+        if let Some(canonical) = aliases.get(prefix) {
+            return format!("{}{}", canonical, &ability[prefix.len()..]);
+        }
+        match prefix.rsplit_once('/') {
+            Some((parent, _)) => prefix = parent,
+            None => return ability.to_owned(),
         }
     }
 }
 
+/// Parses an ability of the general and ownership semantics.
+fn parse_general_ability(
+    ability: &str,
+    strict_namespace: bool,
+    aliases: &AbilityAliases,
+) -> Option<GeneralAbility> {
+    let ability = GeneralAbility::try_from(resolve_alias(aliases, ability)).ok()?;
+    if strict_namespace {
+        ability.into_strict()
+    } else {
+        Some(ability)
+    }
+}
+
+impl TryFrom<String> for GeneralAbility {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Ok(GeneralAbility {
+            ability: value,
+            strict_namespace: false,
+        })
+    }
+}
+
+impl Display for GeneralAbility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let content = &self.ability;
+        write!(f, "{content}")
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct GeneralResource {
     scheme: String,
     path: String,
+    #[serde(skip)]
+    superuser: bool,
 }
 
 impl Scope for GeneralResource {
     fn contains(&self, other: &Self) -> bool {
+        if self.superuser {
+            return true;
+        }
+        if other.superuser {
+            return false;
+        }
         if self.scheme != other.scheme {
             return false;
         }
@@ -185,6 +269,7 @@ impl TryFrom<Url> for GeneralResource {
         Ok(GeneralResource {
             scheme: String::from(value.scheme()),
             path: p1,
+            superuser: false,
         })
     }
 }
@@ -205,6 +290,369 @@ impl Display for GeneralResource {
     }
 }
 
-pub struct GeneralSemantics {}
+#[derive(Default)]
+pub struct GeneralSemantics {
+    /// Require ability namespaces to match exactly, "*" is allowed only after
+    /// a namespace.
+    pub strict_namespace: bool,
+    /// The resource (like "*:*") which includes resources of all schemes.
+    pub superuser_resource: Option<String>,
+    /// Aliases of abilities, resolved when abilities are parsed.
+    pub ability_aliases: AbilityAliases,
+}
+
+impl CapabilitySemantics<GeneralResource, GeneralAbility> for GeneralSemantics {
+    fn parse_resource(&self, resource: &str) -> Option<Resource<GeneralResource>> {
+        if self.superuser_resource.as_deref() == Some(resource) {
+            let (scheme, path) = resource.split_once(':')?;
+            return Some(Resource::ResourceUri(GeneralResource {
+                scheme: scheme.to_owned(),
+                path: path.to_owned(),
+                superuser: true,
+            }));
+        }
+        parse_resource(self, resource)
+    }
+
+    fn parse_action(&self, ability: &str) -> Option<GeneralAbility> {
+        parse_general_ability(ability, self.strict_namespace, &self.ability_aliases)
+    }
+}
+
+/// A resource of the ownership semantics: either resources of a scheme owned
+/// by a DID ("my:<scheme>", "as:<did>:<scheme>"), or a general resource.
+#[derive(Clone, PartialEq, Debug)]
+pub enum OwnershipResource {
+    /// The owner is None for "my:<scheme>" (the issuer), the scheme "*" means
+    /// all schemes.
+    Owned {
+        owner: Option<String>,
+        scheme: String,
+    },
+    Resource(GeneralResource),
+}
+
+impl Scope for OwnershipResource {
+    fn contains(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                OwnershipResource::Owned { owner, scheme },
+                OwnershipResource::Owned {
+                    owner: other_owner,
+                    scheme: other_scheme,
+                },
+            ) => owner == other_owner && (scheme == "*" || scheme == other_scheme),
+            (OwnershipResource::Owned { scheme, .. }, OwnershipResource::Resource(resource)) => {
+                scheme == "*" || scheme == &resource.scheme
+            }
+            (OwnershipResource::Resource(resource), OwnershipResource::Resource(other)) => {
+                resource.contains(other)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl TryFrom<Url> for OwnershipResource {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Url) -> Result<Self, Self::Error> {
+        match value.scheme() {
+            "my" => {
+                let scheme = value.path();
+                if scheme.is_empty() || scheme.contains(':') {
+                    return Err(anyhow!("invalid ownership resource: {}", value));
+                }
+                Ok(OwnershipResource::Owned {
+                    owner: None,
+                    scheme: scheme.to_owned(),
+                })
+            }
+            "as" => match value.path().rsplit_once(':') {
+                Some((owner, scheme)) if owner.starts_with("did:") && !scheme.is_empty() => {
+                    Ok(OwnershipResource::Owned {
+                        owner: Some(owner.to_owned()),
+                        scheme: scheme.to_owned(),
+                    })
+                }
+                _ => Err(anyhow!("invalid ownership resource: {}", value)),
+            },
+            _ => Ok(OwnershipResource::Resource(GeneralResource::try_from(
+                value,
+            )?)),
+        }
+    }
+}
+
+impl Display for OwnershipResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnershipResource::Owned {
+                owner: None,
+                scheme,
+            } => write!(f, "my:{scheme}"),
+            OwnershipResource::Owned {
+                owner: Some(owner),
+                scheme,
+            } => write!(f, "as:{owner}:{scheme}"),
+            OwnershipResource::Resource(resource) => write!(f, "{resource}"),
+        }
+    }
+}
+
+/// The ownership semantics: "my:<scheme>" are resources of the scheme owned
+/// by the issuer of a token, "as:<did>:<scheme>" are resources owned by the
+/// DID (delegated by its "my:<scheme>"). "*" scheme means all schemes. Other
+/// resources and abilities are compared as in [GeneralSemantics].
+#[derive(Default)]
+pub struct OwnershipSemantics {
+    /// See [GeneralSemantics::strict_namespace].
+    pub strict_namespace: bool,
+    /// See [GeneralSemantics::ability_aliases].
+    pub ability_aliases: AbilityAliases,
+}
+
+impl CapabilitySemantics<OwnershipResource, GeneralAbility> for OwnershipSemantics {
+    fn parse_action(&self, ability: &str) -> Option<GeneralAbility> {
+        parse_general_ability(ability, self.strict_namespace, &self.ability_aliases)
+    }
+
+    fn parse_issued_capability(
+        &self,
+        value: &Capability,
+        issuer: &str,
+    ) -> Option<CapabilityView<OwnershipResource, GeneralAbility>> {
+        // "my:" of the issuer is "as:<issuer>:" for others
+        match value.resource.strip_prefix("my:") {
+            Some(scheme) => self.parse(
+                &format!("as:{issuer}:{scheme}"),
+                &value.ability,
+                Some(&value.caveat),
+            ),
+            None => self.parse_capability(value),
+        }
+    }
+}
 
-impl CapabilitySemantics<GeneralResource, GeneralAbility> for GeneralSemantics {}
+/// Options of the registered semantics (not all semantics use them).
+#[derive(Debug, Default, Clone)]
+pub struct SemanticsOptions {
+    /// See [GeneralSemantics::strict_namespace].
+    pub strict_namespace: bool,
+    /// See [GeneralSemantics::superuser_resource].
+    pub superuser_resource: Option<String>,
+    /// See [GeneralSemantics::ability_aliases].
+    pub ability_aliases: AbilityAliases,
+}
+
+/// Names of the semantics compiled in, as selected by "semantics" options.
+pub const SEMANTICS: &[&str] = &[
+    "general",
+    "ownership",
+    "proof",
+    #[cfg(feature = "email-semantics")]
+    "email",
+];
+
+/// Evaluates the body with the semantics registered by the name, returning
+/// the result of the body, or an error if there is no such semantics. To
+/// register a semantics, add its arm (behind a feature, if it's optional),
+/// its name to [SEMANTICS] and its rules to `describeSemantics()`; the tests
+/// of `describe_functions` check that every name resolves and is described.
+///
+/// The `|semantics| body` only looks like a closure: the body is expanded in
+/// each arm of a match, so `?` and `return` in it leave the calling function,
+/// not the body.
+macro_rules! with_semantics {
+    ($name:expr, $options:expr, |$semantics:ident| $body:expr) => {{
+        let options: &$crate::semantics::SemanticsOptions = $options;
+        match $name {
+            "general" => {
+                let $semantics = &$crate::semantics::GeneralSemantics {
+                    strict_namespace: options.strict_namespace,
+                    superuser_resource: options.superuser_resource.clone(),
+                    ability_aliases: options.ability_aliases.clone(),
+                };
+                Ok::<_, ::anyhow::Error>($body)
+            }
+            // only the general semantics has a superuser resource
+            name if options.superuser_resource.is_some()
+                && $crate::semantics::SEMANTICS.contains(&name) =>
+            {
+                Err(::anyhow::anyhow!(
+                    r#"semantics "{}" doesn't support superuserResource"#,
+                    name
+                ))
+            }
+            "ownership" => {
+                let $semantics = &$crate::semantics::OwnershipSemantics {
+                    strict_namespace: options.strict_namespace,
+                    ability_aliases: options.ability_aliases.clone(),
+                };
+                Ok($body)
+            }
+            // only delegation of proofs ("ucan:*" resources)
+            "proof" => {
+                let $semantics = &::ucan::capability::proof::ProofDelegationSemantics {};
+                Ok($body)
+            }
+            #[cfg(feature = "email-semantics")]
+            "email" => {
+                let $semantics = &$crate::email_semantics::EmailSemantics {};
+                Ok($body)
+            }
+            name => Err(::anyhow::anyhow!(
+                r#"unsupported semantics "{}", expected one of: {}"#,
+                name,
+                $crate::semantics::SEMANTICS.join(", ")
+            )),
+        }
+    }};
+}
+pub(crate) use with_semantics;
+
+#[cfg(test)]
+mod tests {
+    use super::{AbilityAliases, GeneralAbility, GeneralSemantics, OwnershipSemantics};
+    use ucan::capability::{Ability, Capability, CapabilitySemantics};
+
+    fn ability(semantics: &GeneralSemantics, ability: &str) -> Option<GeneralAbility> {
+        semantics.parse_action(ability)
+    }
+
+    #[test]
+    fn test_ability_enables() {
+        let semantics = GeneralSemantics::default();
+        let enables = |a: &str, b: &str| {
+            ability(&semantics, a)
+                .unwrap()
+                .enables(&ability(&semantics, b).unwrap())
+        };
+        assert!(enables("user/post", "user/post"));
+        assert!(enables("user/post", "user/post/draft"));
+        assert!(!enables("user/post/draft", "user/post"));
+        assert!(enables("*", "user/post"));
+        assert!(!enables("user/post", "*"));
+        assert!(enables("user/*", "user/post"));
+        assert!(!enables("user/post", "user/*"));
+        assert!(!enables("msg/*", "file/send"));
+        assert!(enables("*/send", "file/send"));
+    }
+
+    #[test]
+    fn test_ability_enables_strict_namespace() {
+        let semantics = GeneralSemantics {
+            strict_namespace: true,
+            ..Default::default()
+        };
+        assert!(ability(&semantics, "*").is_none());
+        assert!(ability(&semantics, "*/send").is_none());
+        assert!(ability(&semantics, "user").is_none());
+
+        let enables = |a: &str, b: &str| {
+            ability(&semantics, a)
+                .unwrap()
+                .enables(&ability(&semantics, b).unwrap())
+        };
+        assert!(enables("user/post", "user/post/draft"));
+        assert!(enables("user/*", "user/post"));
+        assert!(!enables("user/post", "user/*"));
+        assert!(!enables("msg/*", "file/send"));
+    }
+
+    #[test]
+    fn test_ability_aliases() {
+        let semantics = GeneralSemantics {
+            ability_aliases: AbilityAliases::from([
+                ("fetch".to_owned(), "crud/read".to_owned()),
+                ("msg/send".to_owned(), "mail/send".to_owned()),
+            ]),
+            ..Default::default()
+        };
+        let name = |a: &str| ability(&semantics, a).unwrap().to_string();
+        assert_eq!(name("fetch"), "crud/read");
+        assert_eq!(name("msg/send"), "mail/send");
+        assert_eq!(name("msg/send/draft"), "mail/send/draft");
+        assert_eq!(name("msg/sendall"), "msg/sendall");
+        assert_eq!(name("msg/*"), "msg/*");
+
+        let enables = |a: &str, b: &str| {
+            ability(&semantics, a)
+                .unwrap()
+                .enables(&ability(&semantics, b).unwrap())
+        };
+        assert!(enables("msg/send", "mail/send/draft"));
+        assert!(enables("mail/send", "msg/send"));
+        assert!(enables("fetch", "crud/read"));
+        assert!(!enables("fetch", "crud/write"));
+    }
+
+    #[test]
+    fn test_superuser_resource() {
+        let semantics = GeneralSemantics {
+            superuser_resource: Some("*:*".to_owned()),
+            ..Default::default()
+        };
+        let capability = |resource: &str| {
+            semantics
+                .parse_capability(&Capability::new(
+                    resource.to_owned(),
+                    "user/post".to_owned(),
+                    serde_json::json!({}),
+                ))
+                .unwrap()
+        };
+        let superuser = capability("*:*");
+        assert_eq!(superuser.resource.to_string(), "*:*");
+        assert!(superuser.enables(&capability("api:user/1")));
+        assert!(superuser.enables(&capability("mailto:alice@example.com")));
+        assert!(!capability("api:*").enables(&superuser));
+
+        let semantics = GeneralSemantics::default();
+        assert!(semantics
+            .parse_capability(&Capability::new(
+                "*:*".to_owned(),
+                "user/post".to_owned(),
+                serde_json::json!({}),
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn test_ownership_semantics() {
+        let semantics = OwnershipSemantics::default();
+        let root = "did:key:z6MkmftLP2owSZPneufrtWF7t9j2HL7sXYVgoroM59sjD7yx";
+        let alice = "did:key:z6Mkmup4Wyv9kXKrmy1DB2bLhaviKSgPGSqjC1gCEtWoHjAY";
+        let capability = |resource: &str| {
+            Capability::new(
+                resource.to_owned(),
+                "user/post".to_owned(),
+                serde_json::json!({}),
+            )
+        };
+
+        let owned = semantics
+            .parse_issued_capability(&capability("my:api"), root)
+            .unwrap();
+        assert_eq!(owned.resource.to_string(), format!("as:{root}:api"));
+        let delegated = semantics
+            .parse_issued_capability(&capability(&format!("as:{root}:api")), alice)
+            .unwrap();
+        assert!(owned.enables(&delegated));
+        let api = semantics
+            .parse_capability(&capability("api:user/1"))
+            .unwrap();
+        assert!(owned.enables(&api));
+        assert!(!owned.enables(&semantics.parse_capability(&capability("doc:1")).unwrap()));
+        assert!(!api.enables(&owned));
+
+        let alice_owned = semantics
+            .parse_issued_capability(&capability("my:*"), alice)
+            .unwrap();
+        assert!(!alice_owned.enables(&delegated));
+        assert!(alice_owned.enables(&api));
+
+        assert!(semantics.parse_capability(&capability("as:api")).is_none());
+        assert!(semantics.parse_capability(&capability("my:")).is_none());
+    }
+}
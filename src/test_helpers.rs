@@ -0,0 +1,43 @@
+//! Fixtures shared by the tests of the modules.
+
+use crate::ucan_functions::{build_ucan, decode_proofs, get_key_material, InvokeOptions};
+use anyhow::Result;
+use did_key::{generate, Config, DIDCore, Ed25519KeyPair, VerificationMethod};
+use once_cell::sync::OnceCell;
+use serde_json::json;
+
+pub fn new_identity() -> (String, VerificationMethod) {
+    let key = generate::<Ed25519KeyPair>(None);
+    let did_doc = key.get_did_document(Config {
+        use_jose_format: false,
+        serialize_secrets: true,
+    });
+    (did_doc.id, did_doc.verification_method[0].clone())
+}
+
+// the same for every token, so a delegation never outlives its proofs
+// when the clock ticks between two of them
+pub fn expiration() -> u64 {
+    static EXPIRATION: OnceCell<u64> = OnceCell::new();
+    *EXPIRATION.get_or_init(|| ucan::time::now() + 3600)
+}
+
+pub fn invoke_options(
+    issuer: &VerificationMethod,
+    audience: &str,
+    capabilities: &serde_json::Value,
+) -> InvokeOptions {
+    serde_json::from_value(json!({
+        "issuer": issuer,
+        "audience": audience,
+        "expiration": expiration(),
+        "capabilities": capabilities,
+    }))
+    .unwrap()
+}
+
+pub async fn invoke(config: InvokeOptions) -> Result<String> {
+    let key_material = get_key_material(&config.issuer)?;
+    let proofs = decode_proofs(&config)?;
+    build_ucan(config, proofs, key_material).await
+}
@@ -1,22 +1,32 @@
 use crate::did_functions::get_keys;
-use crate::runtime;
-use crate::semantics::GeneralSemantics;
+use crate::disclosure_functions::{merge_disclosed_facts, DisclosedUcan};
+use crate::expansion_functions::{expand_requirement, render_requirement};
+use crate::guard_functions::{guard_delegation_depth, guard_proof_lifetime};
+use crate::ledger::{lock_ledger, SharedLedger};
+use crate::lint_functions::lint_invoke_options;
+use crate::namespace_functions::store_namespace;
+use crate::semantics::{with_semantics, AbilityAliases, SemanticsOptions};
+use crate::{runtime, verification_limit};
 use anyhow::{anyhow, Result};
+use base64::Engine;
 use did_key::KeyFormat;
 use did_key::VerificationMethod;
 use neon::prelude::*;
 use neon::types::JsPromise;
 use serde::{Deserialize, Serialize};
-use tinytemplate::TinyTemplate;
-use ucan::store::{MemoryStore, UcanJwtStore};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use ucan::store::{LayeredStore, MemoryStore, UcanJwtStore};
 use ucan::{
     builder::UcanBuilder,
-    capability::{Capabilities, Capability, CapabilitySemantics},
-    chain::ProofChain,
+    capability::{Ability, Capabilities, Capability, CapabilitySemantics, Caveat, Scope},
+    chain::{CapabilityInfo, ParseFailureMode, ProofChain, ProofSource, ResolveOptions},
     crypto::did::{
-        DidParser, KeyConstructorSlice, ED25519_MAGIC_BYTES, P256_MAGIC_BYTES, RSA_MAGIC_BYTES,
+        normalize_did, DidParser, KeyConstructorSlice, ED25519_MAGIC_BYTES, P256_MAGIC_BYTES,
+        RSA_MAGIC_BYTES,
     },
-    crypto::KeyMaterial,
+    crypto::{KeyMaterial, SignatureCache},
+    disclosure::DIGESTS_FACT,
     ucan::{Code, FactsMap},
     Ucan,
 };
@@ -26,6 +36,9 @@ use ucan_key_support::{
     rsa::bytes_to_rsa_key,
 };
 
+/// The template variable holding facts of the verifier.
+pub const CONTEXT_FACT: &str = "ctx";
+
 pub const SUPPORTED_KEYS: &KeyConstructorSlice = &[
     // https://github.com/multiformats/multicodec/blob/e9ecf587558964715054a0afcc01f7ace220952c/table.csv#L94
     (ED25519_MAGIC_BYTES, bytes_to_ed25519_key),
@@ -33,6 +46,24 @@ pub const SUPPORTED_KEYS: &KeyConstructorSlice = &[
     (P256_MAGIC_BYTES, bytes_to_p256_key),
 ];
 
+/// Max number of successful signature verifications to remember.
+pub const SIGNATURE_CACHE_SIZE: usize = 10_000;
+
+/// Max number of proofs a store keeps, forgetting the oldest ones first.
+pub const PROOF_STORE_CAPACITY: usize = 100_000;
+
+/// Creates an empty store of proofs, which forgets expired proofs and the
+/// oldest ones past [PROOF_STORE_CAPACITY].
+pub fn new_proof_store() -> MemoryStore {
+    MemoryStore::with_capacity(PROOF_STORE_CAPACITY)
+}
+
+/// Creates an empty signature cache, remembering at most
+/// [SIGNATURE_CACHE_SIZE] verifications.
+pub fn new_signature_cache() -> Arc<SignatureCache> {
+    Arc::new(SignatureCache::new(SIGNATURE_CACHE_SIZE))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InvokeOptions {
     pub issuer: VerificationMethod,
@@ -47,80 +78,208 @@ pub struct InvokeOptions {
     pub add_nonce: Option<bool>,
     #[serde(rename = "addProofFacts")]
     pub add_proof_facts: Option<bool>,
+    #[serde(rename = "delegateAllFrom")]
+    pub delegate_all_from: Option<Vec<String>>,
+    #[serde(rename = "proofHasher")]
+    pub proof_hasher: Option<String>,
+    #[serde(rename = "witnessedBy")]
+    pub witnessed_by: Option<Vec<WitnessOptions>>,
+    pub typ: Option<String>,
+    pub header: Option<FactsMap>,
+    pub strict: Option<bool>,
+    #[serde(rename = "superuserResource")]
+    pub superuser_resource: Option<String>,
+    pub semantics: Option<String>,
+    #[serde(rename = "proofLifetime")]
+    pub proof_lifetime: Option<String>,
+    #[serde(rename = "canonicalJson")]
+    pub canonical_json: Option<bool>,
+    #[serde(rename = "hiddenFacts")]
+    pub hidden_facts: Option<Vec<String>>,
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+    /// The ledger of the store namespace, recording the nonce added with
+    /// `addNonce`.
+    #[serde(skip)]
+    pub ledger: SharedLedger,
+    /// The nonce added with `addNonce`, instead of a random one.
+    #[serde(skip)]
+    pub nonce: Option<String>,
+    /// The store of the store namespace, resolving proofs of the proofs in
+    /// strict mode.
+    #[serde(skip)]
+    pub store: Option<MemoryStore>,
 }
 
-pub fn invoke_ucan(mut cx: FunctionContext) -> JsResult<JsPromise> {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WitnessOptions {
+    pub token: String,
+    pub hasher: Option<String>,
+}
+
+/// Proof tokens of [InvokeOptions], decoded before building a UCAN.
+pub struct InvokeProofs {
+    pub hasher: Option<Code>,
+    pub proofs: Option<Vec<Ucan>>,
+    pub delegations: Option<Vec<Ucan>>,
+    pub witnesses: Vec<(Ucan, Option<Code>)>,
+}
+
+/// Issues a token, resolving to it as a string.
+pub fn invoke_ucan(cx: FunctionContext) -> JsResult<JsPromise> {
+    spawn_invoke_ucan(cx, false)
+}
+
+/// Issues a token, resolving to it with the disclosures of its hidden facts.
+pub fn invoke_ucan_with_disclosures(cx: FunctionContext) -> JsResult<JsPromise> {
+    spawn_invoke_ucan(cx, true)
+}
+
+fn spawn_invoke_ucan(mut cx: FunctionContext, with_disclosures: bool) -> JsResult<JsPromise> {
     let config: Handle<neon::types::JsValue> = cx.argument(0)?;
-    let config: InvokeOptions =
+    let mut config: InvokeOptions =
         neon_serde2::from_value(&mut cx, config).or_else(|e| cx.throw_error(e.to_string()))?;
+    if config.hidden_facts.is_some() && !with_disclosures {
+        // the disclosures would be lost
+        return cx.throw_error(r#""hiddenFacts" requires invokeUcanWithDisclosures"#);
+    }
+    let namespace = store_namespace(&mut cx, config.store_namespace.as_deref())?;
+    config.ledger = namespace.ledger;
+    config.store = Some(namespace.store);
+
+    if config.strict.unwrap_or(false) {
+        let problems = lint_invoke_options(&config);
+        if !problems.is_empty() {
+            return cx.throw_error(format!("invalid options: {}", problems.join("; ")));
+        }
+    }
+
+    let key_material =
+        get_key_material(&config.issuer).or_else(|e| cx.throw_error(e.to_string()))?;
 
-    let verification_method = &config.issuer;
+    let proofs = decode_proofs(&config).or_else(|e| cx.throw_error(e.to_string()))?;
 
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = build_disclosed_ucan(config, proofs, key_material).await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(result) if with_disclosures => {
+                neon_serde2::to_value(&mut cx, &result).or_else(|e| cx.throw_error(e.to_string()))
+            }
+            Ok(result) => Ok(cx.string(result.token).upcast()),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+pub fn get_key_material(verification_method: &VerificationMethod) -> Result<Box<dyn KeyMaterial>> {
     let keys = get_keys(
         &verification_method.public_key,
         &verification_method.private_key,
-    )
-    .or_else(|e| cx.throw_error(e.to_string()))?;
+    )?;
 
     let mut key_type = verification_method.key_type.clone();
     if key_type == "JsonWebKey2020" {
         if let Some(KeyFormat::JWK(k)) = &verification_method.private_key {
             key_type.clone_from(&k.curve);
         } else {
-            return cx.throw_error("invalid private key");
+            return Err(anyhow!("invalid private key"));
         }
     }
 
-    let key_material = match key_type.as_str() {
+    match key_type.as_str() {
         "Ed25519" | "Ed25519VerificationKey2018" => bytes_to_ed25519_private_key(keys.1),
         "P256" | "UnsupportedVerificationMethod2020" | "P-256" => bytes_to_p256_private_key(keys.1),
-        _ => {
-            return cx.throw_error(format!(r#"unsupported key type: "{}""#, key_type));
-        }
-    };
-
-    let key_material = key_material.or_else(|e| cx.throw_error(e.to_string()))?;
+        _ => Err(anyhow!(r#"unsupported key type: "{}""#, key_type)),
+    }
+}
 
-    let proofs = match &config.proofs {
-        Some(proofs) => {
+fn decode_tokens(tokens: &Option<Vec<String>>) -> Result<Option<Vec<Ucan>>> {
+    match tokens {
+        Some(tokens) => {
             let mut ucans: Vec<Ucan> = Vec::new();
-            for token in proofs.iter() {
-                ucans.push(
-                    Ucan::try_from(token.clone()).or_else(|e| cx.throw_error(e.to_string()))?,
-                );
+            for token in tokens.iter() {
+                ucans.push(Ucan::try_from(token.as_str())?);
             }
-            Some(ucans)
+            Ok(Some(ucans))
         }
-        None => None,
-    };
-
-    // Construct a result promise which will be fulfilled when the computation completes.
-    let (deferred, promise) = cx.promise();
-    let channel = cx.channel();
-    let runtime = runtime(&mut cx)?;
-
-    // Perform the computation in a background thread using the Tokio thread pool.
-    runtime.spawn(async move {
-        let result = build_ucan(config, proofs, key_material).await;
+        None => Ok(None),
+    }
+}
 
-        // Resolve the result promise with the result of the computation.
-        deferred.settle_with(&channel, |mut cx| match result {
-            Ok(result) => Ok(cx.string(result)),
-            Err(e) => cx.throw_error(e.to_string()),
-        });
-    });
+/// Parses a multihash name, as used in CIDs, into a hasher code.
+pub fn parse_hasher(name: &str) -> Result<Code> {
+    match name {
+        "blake3-256" | "blake3" => Ok(Code::Blake3_256),
+        "sha2-256" | "sha256" => Ok(Code::Sha2_256),
+        "sha2-512" | "sha512" => Ok(Code::Sha2_512),
+        "blake2b-256" => Ok(Code::Blake2b256),
+        "blake2b-512" => Ok(Code::Blake2b512),
+        _ => Err(anyhow!(r#"unsupported hasher: "{}""#, name)),
+    }
+}
 
-    Ok(promise)
+pub fn decode_proofs(config: &InvokeOptions) -> Result<InvokeProofs> {
+    let hasher = match &config.proof_hasher {
+        Some(hasher) => Some(parse_hasher(hasher)?),
+        None => None,
+    };
+    let mut witnesses = Vec::new();
+    if let Some(witnessed_by) = &config.witnessed_by {
+        for witness in witnessed_by.iter() {
+            let witness_hasher = match &witness.hasher {
+                Some(hasher) => Some(parse_hasher(hasher)?),
+                None => hasher,
+            };
+            witnesses.push((Ucan::try_from(witness.token.as_str())?, witness_hasher));
+        }
+    }
+    Ok(InvokeProofs {
+        hasher,
+        proofs: decode_tokens(&config.proofs)?,
+        delegations: decode_tokens(&config.delegate_all_from)?,
+        witnesses,
+    })
 }
 
-async fn build_ucan(
+pub async fn build_ucan(
     config: InvokeOptions,
-    proofs: Option<Vec<Ucan>>,
+    proofs: InvokeProofs,
     key_material: Box<dyn KeyMaterial>,
 ) -> Result<String> {
+    Ok(build_disclosed_ucan(config, proofs, key_material)
+        .await?
+        .token)
+}
+
+pub async fn build_disclosed_ucan(
+    mut config: InvokeOptions,
+    proofs: InvokeProofs,
+    key_material: Box<dyn KeyMaterial>,
+) -> Result<DisclosedUcan> {
+    if let Some(mode) = &config.proof_lifetime {
+        let clamp = match mode.as_str() {
+            "clamp" => true,
+            "error" => false,
+            mode => return Err(anyhow!(r#"unsupported proof lifetime mode "{}""#, mode)),
+        };
+        guard_proof_lifetime(&mut config, &proofs, clamp)?;
+    }
+    if config.strict.unwrap_or(false) {
+        guard_delegation_depth(&config, &proofs).await?;
+    }
     let mut builder = UcanBuilder::default()
         .issued_by(&key_material)
-        .for_audience(&config.audience)
+        .for_audience(normalize_did(&config.audience))
         .with_expiration(config.expiration)
         .claiming_capabilities(&config.capabilities.iter().collect::<Vec<Capability>>());
     if let Some(add_proof_facts) = &config.add_proof_facts {
@@ -132,23 +291,72 @@ async fn build_ucan(
         builder = builder.not_before(*not_before);
     }
     if let Some(facts) = &config.facts {
+        let hidden_facts = config.hidden_facts.as_deref().unwrap_or_default();
         builder = builder.with_facts(
             &facts
                 .iter()
+                .filter(|x| !hidden_facts.contains(x.0))
                 .map(|x| (x.0.clone(), x.1.clone()))
                 .collect::<Vec<(String, serde_json::Value)>>(),
         );
     }
-    if let Some(proofs) = &proofs {
-        builder = builder.with_proofs(proofs, None)?;
+    for name in config.hidden_facts.iter().flatten() {
+        let fact = config
+            .facts
+            .as_ref()
+            .and_then(|facts| facts.get(name))
+            .ok_or_else(|| anyhow!(r#"no hidden fact "{}""#, name))?;
+        builder = builder.with_hidden_fact(name, fact)?;
+    }
+    if let Some(typ) = &config.typ {
+        builder = builder.with_type(typ);
+    }
+    for (key, value) in config.header.iter().flatten() {
+        builder = builder.with_header_field(key, value)?;
+    }
+    if let Some(canonical_json) = &config.canonical_json {
+        builder = builder.with_canonical_json(*canonical_json);
+    }
+    if let Some(ucans) = &proofs.proofs {
+        builder = builder.with_proofs(ucans, proofs.hasher)?;
+    }
+    for (ucan, hasher) in proofs.witnesses.iter() {
+        builder = builder.witnessed_by(ucan, *hasher)?;
+    }
+    if let Some(delegations) = &proofs.delegations {
+        // each delegation adds the proof and the "ucan:<cid>" capability for it
+        for delegation in delegations.iter() {
+            builder = builder.delegating_from(delegation, proofs.hasher)?;
+        }
     }
     if let Some(add_nonce) = &config.add_nonce {
         if *add_nonce {
             builder = builder.with_nonce();
+            if let Some(nonce) = &config.nonce {
+                builder = builder.with_nonce_value(nonce);
+            }
         }
     }
-    let result: String = builder.build()?.sign().await?.encode()?;
-    Ok(result)
+    let signable = builder.build()?;
+    // fail before signing a token with a nonce already issued
+    if let Some(nonce) = &signable.nonce {
+        lock_ledger(&config.ledger)?.check_nonce(nonce, ucan::time::now())?;
+    }
+    let disclosures = signable.disclosures.clone();
+    let ucan = signable.sign().await?;
+    if let Some(nonce) = ucan.nonce() {
+        let cid = ucan.to_cid(Code::Blake3_256)?;
+        lock_ledger(&config.ledger)?.record(
+            &cid.to_string(),
+            nonce,
+            config.expiration,
+            ucan::time::now(),
+        )?;
+    }
+    Ok(DisclosedUcan {
+        token: ucan.encode()?,
+        disclosures,
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -162,6 +370,33 @@ pub struct VerifyOptions {
     pub required_facts: Option<FactsMap>,
     #[serde(rename = "knownTokens")]
     pub known_tokens: Option<Vec<String>>,
+    #[serde(rename = "strictNamespaces")]
+    pub strict_namespaces: Option<bool>,
+    #[serde(rename = "superuserResource")]
+    pub superuser_resource: Option<String>,
+    pub semantics: Option<String>,
+    #[serde(rename = "abilityAliases")]
+    pub ability_aliases: Option<AbilityAliases>,
+    #[serde(rename = "factsScope")]
+    pub facts_scope: Option<String>,
+    #[serde(rename = "storeProofs")]
+    pub store_proofs: Option<bool>,
+    pub expansion: Option<String>,
+    #[serde(rename = "strictAudienceChain")]
+    pub strict_audience_chain: Option<bool>,
+    pub disclosures: Option<Vec<String>>,
+    #[serde(rename = "parseFailure")]
+    pub parse_failure: Option<String>,
+    pub context: Option<FactsMap>,
+    #[serde(rename = "atTime")]
+    pub at_time: Option<u64>,
+    #[serde(rename = "storeNamespace")]
+    pub store_namespace: Option<String>,
+    #[serde(rename = "lenientDecode")]
+    pub lenient_decode: Option<bool>,
+    /// The store of the addon instance or namespace, used with `storeProofs`.
+    #[serde(skip)]
+    pub proof_store: Option<MemoryStore>,
 }
 
 #[derive(Debug, Serialize)]
@@ -169,6 +404,49 @@ pub struct VerifyResponse {
     pub capabilities: Capabilities,
     pub facts: Option<FactsMap>,
     pub cids: Vec<String>,
+    #[serde(rename = "proofSources")]
+    pub proof_sources: BTreeMap<String, String>,
+    /// Capabilities skipped with the "skip" parse failure mode.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// The issuer of the leaf token.
+    pub issuer: String,
+    /// The audience of the leaf token.
+    pub audience: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    /// The UCAN version of the leaf token.
+    pub version: String,
+}
+
+/// The result of a required capability check.
+#[derive(Debug, Serialize)]
+pub struct RequirementResult {
+    pub resource: String,
+    pub ability: String,
+    pub ok: bool,
+    /// The capability with the matching resource which failed the least.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nearest: Option<NearestCapability>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NearestCapability {
+    pub resource: String,
+    pub ability: String,
+    pub caveat: serde_json::Value,
+    /// What doesn't match: "ability", "caveat" or "originator".
+    pub mismatch: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub requirements: Vec<RequirementResult>,
+    #[serde(flatten)]
+    pub verification: Option<VerifyResponse>,
 }
 
 pub fn verify_ucan(mut cx: FunctionContext) -> JsResult<JsPromise> {
@@ -176,19 +454,27 @@ pub fn verify_ucan(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
     let token: String = token.value(&mut cx);
     let config: Handle<neon::types::JsValue> = cx.argument(1)?;
-    let config: VerifyOptions =
+    let mut config: VerifyOptions =
         neon_serde2::from_value(&mut cx, config).or_else(|e| cx.throw_error(e.to_string()))?;
+    let namespace = store_namespace(&mut cx, config.store_namespace.as_deref())?;
+    if config.store_proofs.unwrap_or(false) {
+        config.proof_store = Some(namespace.store);
+    }
 
-    let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+    let mut did_parser =
+        DidParser::new(SUPPORTED_KEYS).with_signature_cache(namespace.signature_cache);
 
     // Construct a result promise which will be fulfilled when the computation completes.
     let (deferred, promise) = cx.promise();
     let channel = cx.channel();
     let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
 
     // Perform the computation in a background thread using the Tokio thread pool.
     runtime.spawn(async move {
-        let result = internal_verify_ucan(&token, config, &mut did_parser).await;
+        let result = limit
+            .run(internal_verify_ucan(&token, config, &mut did_parser))
+            .await;
 
         // Resolve the result promise with the result of the computation.
         deferred.settle_with(&channel, |mut cx| match result {
@@ -203,25 +489,158 @@ pub fn verify_ucan(mut cx: FunctionContext) -> JsResult<JsPromise> {
     Ok(promise)
 }
 
-async fn internal_verify_ucan(
+pub fn check_ucan(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let token: Handle<neon::types::JsValue> = cx.argument(0)?;
+    let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
+    let token: String = token.value(&mut cx);
+    let config: Handle<neon::types::JsValue> = cx.argument(1)?;
+    let mut config: VerifyOptions =
+        neon_serde2::from_value(&mut cx, config).or_else(|e| cx.throw_error(e.to_string()))?;
+    let namespace = store_namespace(&mut cx, config.store_namespace.as_deref())?;
+    if config.store_proofs.unwrap_or(false) {
+        config.proof_store = Some(namespace.store);
+    }
+
+    let mut did_parser =
+        DidParser::new(SUPPORTED_KEYS).with_signature_cache(namespace.signature_cache);
+
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = limit
+            .run(async { Ok(internal_check_ucan(&token, config, &mut did_parser).await) })
+            .await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, move |mut cx| match result {
+            Ok(result) => match neon_serde2::to_value(&mut cx, &result) {
+                Ok(result) => Ok(result),
+                Err(e) => cx.throw_error(e.to_string()),
+            },
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+pub async fn internal_verify_ucan(
+    token: &str,
+    config: VerifyOptions,
+    did_parser: &mut DidParser,
+) -> Result<VerifyResponse> {
+    verify_chain(token, config, did_parser, &mut Vec::new()).await
+}
+
+/// Verifies the token like [internal_verify_ucan], but reports failures
+/// instead of returning them as errors.
+pub async fn internal_check_ucan(
+    token: &str,
+    config: VerifyOptions,
+    did_parser: &mut DidParser,
+) -> CheckResponse {
+    let mut requirements = Vec::new();
+    match verify_chain(token, config, did_parser, &mut requirements).await {
+        Ok(verification) => CheckResponse {
+            ok: true,
+            error: None,
+            requirements,
+            verification: Some(verification),
+        },
+        Err(e) => CheckResponse {
+            ok: false,
+            error: Some(e.to_string()),
+            requirements,
+            verification: None,
+        },
+    }
+}
+
+async fn verify_chain(
     token: &str,
     config: VerifyOptions,
     did_parser: &mut DidParser,
+    requirements: &mut Vec<RequirementResult>,
 ) -> Result<VerifyResponse> {
-    // TODO use global store
-    let mut store = MemoryStore::default();
-    if let Some(proofs) = config.known_tokens {
+    let options = ResolveOptions {
+        lenient: config.lenient_decode.unwrap_or(false),
+        ..Default::default()
+    };
+    let chain = resolve_proof_chain(
+        token,
+        options,
+        &config.known_tokens,
+        config.at_time,
+        config.proof_store.as_ref(),
+        did_parser,
+    )
+    .await?;
+    verify_proof_chain(&chain, &config, requirements)
+}
+
+/// Parses the token and its proofs, validating signatures and time bounds
+/// (at the given time, or now), decoding them as the options tell. With a
+/// shared `proof_store`, proofs are also read from it, and all the resolved
+/// proofs are written into it.
+pub async fn resolve_proof_chain(
+    token: &str,
+    options: ResolveOptions,
+    known_tokens: &Option<Vec<String>>,
+    at_time: Option<u64>,
+    proof_store: Option<&MemoryStore>,
+    did_parser: &mut DidParser,
+) -> Result<ProofChain> {
+    // the known tokens are only kept in the shared store once verified as
+    // proofs of the chain
+    let mut store = LayeredStore::new(proof_store.cloned().unwrap_or_default());
+    if let Some(proofs) = known_tokens {
         for proof in proofs.iter() {
             store.write_token(proof).await?;
         }
     }
-    let chain = ProofChain::try_from_token_string(token, None, did_parser, &store).await?;
-    if chain.ucan().audience() != config.audience {
+    let ucan = match options.lenient {
+        true => Ucan::from_str_lenient(token)?,
+        false => Ucan::try_from(token)?,
+    };
+    let chain = ProofChain::from_ucan_with(ucan, at_time, did_parser, &store, options).await?;
+    if let Some(proof_store) = proof_store {
+        let mut proof_store = proof_store.clone();
+        let mut proofs: Vec<&ProofChain> = chain.proofs().iter().collect();
+        while let Some(proof) = proofs.pop() {
+            proof_store.write_ucan(proof.ucan())?;
+            proofs.extend(proof.proofs().iter());
+        }
+    }
+    Ok(chain)
+}
+
+/// Checks the audience, required facts and capabilities of a resolved chain.
+pub fn verify_proof_chain(
+    chain: &ProofChain,
+    config: &VerifyOptions,
+    requirements: &mut Vec<RequirementResult>,
+) -> Result<VerifyResponse> {
+    if normalize_did(chain.ucan().audience()) != normalize_did(&config.audience) {
         return Err(anyhow!("invalid audience"));
     }
+    if config.strict_audience_chain.unwrap_or(false) {
+        check_audience_chain(chain)?;
+    }
 
-    let mut facts = chain.ucan().facts().clone().unwrap_or(FactsMap::new());
-    merge_facts(&chain, &mut facts);
+    let mut links = Vec::new();
+    match config.facts_scope.as_deref().unwrap_or("merged") {
+        "merged" => chain_links(chain, None, &mut links),
+        "leaf" => links.push(chain.ucan()),
+        "root" => chain_links(chain, Some(normalize_did(&config.root_issuer)), &mut links),
+        facts_scope => return Err(anyhow!(r#"unsupported facts scope "{}""#, facts_scope)),
+    }
+    let disclosures = config.disclosures.as_deref().unwrap_or_default();
+    let mut facts = merge_disclosed_facts(&links, disclosures)?;
     if let Some(required_facts) = &config.required_facts {
         for required_fact in required_facts.iter() {
             match facts.get(required_fact.0) {
@@ -248,91 +667,263 @@ async fn internal_verify_ucan(
     }
     facts.remove("prf");
 
-    let semantics = GeneralSemantics {};
-    let capabilities = chain.reduce_capabilities(&semantics);
+    // templates are rendered with the verifier context, which facts of the
+    // tokens can't override
+    let mut template_facts = facts.clone();
+    template_facts.insert(
+        CONTEXT_FACT.to_owned(),
+        serde_json::to_value(verifier_context(config))?,
+    );
 
-    for required_capability in config.required_capabilities.iter() {
-        let mut tt = TinyTemplate::new();
-        tt.add_template("resource", &required_capability.resource)?;
-        let resource = tt.render("resource", &facts)?;
-        tt.add_template("ability", &required_capability.ability)?;
-        let ability = tt.render("ability", &facts)?;
-
-        let mut caveat = required_capability.caveat;
-        if let Some(obj) = caveat.as_object_mut() {
-            let obj_copy = obj.clone();
-
-            for obj_item in obj_copy.iter() {
-                if let Some(s) = obj_item.1.as_str() {
-                    if s.contains('{') {
-                        let mut tt = TinyTemplate::new();
-                        tt.add_template("caveat", s)?;
-                        let new_s = tt.render("caveat", &facts)?;
-                        if new_s != s {
-                            obj.insert(obj_item.0.to_owned(), serde_json::Value::String(new_s));
-                        }
-                    }
-                }
+    let options = SemanticsOptions {
+        strict_namespace: config.strict_namespaces.unwrap_or(false),
+        superuser_resource: config.superuser_resource.clone(),
+        ability_aliases: config.ability_aliases.clone().unwrap_or_default(),
+    };
+    let mut warnings = Vec::new();
+    let c = with_semantics!(
+        config.semantics.as_deref().unwrap_or("general"),
+        &options,
+        |semantics| check_capabilities(
+            chain,
+            semantics,
+            config,
+            &template_facts,
+            requirements,
+            &mut warnings,
+        )?
+    )?;
+
+    let mut cids = Vec::new();
+    merge_cids(chain, &mut cids)?;
+
+    let mut proof_sources = BTreeMap::new();
+    for proof in chain.proofs() {
+        merge_proof_sources(proof, &mut proof_sources)?;
+    }
+
+    Ok(VerifyResponse {
+        capabilities: c,
+        facts: if !facts.is_empty() { Some(facts) } else { None },
+        cids,
+        proof_sources,
+        warnings,
+        issuer: chain.ucan().issuer().to_owned(),
+        audience: chain.ucan().audience().to_owned(),
+        nonce: chain.ucan().nonce().clone(),
+        version: chain.ucan().version().to_owned(),
+    })
+}
+
+/// Returns facts of the verifier ("ctx.*" in templates): the given context,
+/// the audience ("aud") and the verification time ("now").
+fn verifier_context(config: &VerifyOptions) -> FactsMap {
+    let mut context = config.context.clone().unwrap_or_default();
+    context
+        .entry("aud".to_owned())
+        .or_insert_with(|| serde_json::json!(normalize_did(&config.audience)));
+    context
+        .entry("now".to_owned())
+        .or_insert_with(|| serde_json::json!(config.at_time.unwrap_or_else(ucan::time::now)));
+    context
+}
+
+/// Requires the leaf to be an invocation, claiming no delegation of proofs
+/// ("ucan:" resources), and each link's audience to be the next issuer.
+fn check_audience_chain(chain: &ProofChain) -> Result<()> {
+    if chain
+        .ucan()
+        .capabilities()
+        .iter()
+        .any(|capability| capability.resource.starts_with("ucan:"))
+    {
+        return Err(anyhow!("invalid audience chain: proofs are delegated"));
+    }
+    let mut links = vec![chain];
+    while let Some(link) = links.pop() {
+        for proof in link.proofs() {
+            if normalize_did(proof.ucan().audience()) != normalize_did(link.ucan().issuer()) {
+                return Err(anyhow!(
+                    "invalid audience chain: audience {} is not issuer {}",
+                    proof.ucan().audience(),
+                    link.ucan().issuer()
+                ));
             }
+            links.push(proof);
         }
+    }
+    Ok(())
+}
 
-        let cap = Capability::new(resource.clone(), ability, caveat);
-        let view = semantics.parse_capability(&cap);
-        if view.is_none() {
-            return Err(anyhow!(
-                r#"no capability "{} {}""#,
-                required_capability.resource,
-                required_capability.ability
-            ));
-        }
-        let view = view.unwrap();
-        let mut ok = false;
-        for c in capabilities.iter() {
-            // IMPORTANT! check the originator!
-            if c.capability.enables(&view) && c.originators.contains(&config.root_issuer) {
-                ok = true;
-                break;
+/// Checks required capabilities against the capabilities of the chain,
+/// returning all the capabilities. The result of each check is added to
+/// `requirements`, and skipped capabilities to `warnings`.
+fn check_capabilities<Semantics, S, A>(
+    chain: &ProofChain,
+    semantics: &Semantics,
+    config: &VerifyOptions,
+    facts: &FactsMap,
+    requirements: &mut Vec<RequirementResult>,
+    warnings: &mut Vec<String>,
+) -> Result<Capabilities>
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    let mode = match config.parse_failure.as_deref().unwrap_or("truncate") {
+        "truncate" => ParseFailureMode::Truncate,
+        "skip" => ParseFailureMode::Skip,
+        "fail" => ParseFailureMode::Fail,
+        mode => return Err(anyhow!(r#"unsupported parse failure mode "{}""#, mode)),
+    };
+    let capabilities = chain.reduce_capabilities_with(semantics, mode, warnings)?;
+
+    let any = match config.expansion.as_deref().unwrap_or("all") {
+        "all" => false,
+        "any" => true,
+        expansion => return Err(anyhow!(r#"unsupported expansion "{}""#, expansion)),
+    };
+    let mut failed = None;
+    for required_capability in config.required_capabilities.iter() {
+        let mut results = Vec::new();
+        let mut context = facts.clone();
+        for (required_capability, items) in expand_requirement(&required_capability, facts)? {
+            for (index, item) in items.into_iter().enumerate() {
+                context.insert(format!("expansion_{}", index), item);
             }
-        }
-        if !ok {
-            return Err(anyhow!(
-                r#"no capability "{} {}""#,
-                resource,
-                required_capability.ability
+            let required_capability = render_requirement(&required_capability, &context)?;
+            results.push(check_requirement(
+                semantics,
+                &capabilities,
+                required_capability,
+                config,
             ));
         }
+        let ok = if any {
+            results.iter().any(|result| result.ok)
+        } else {
+            results.iter().all(|result| result.ok)
+        };
+        if !ok && failed.is_none() {
+            failed = results
+                .iter()
+                .find(|result| !result.ok)
+                .map(|result| format!(r#"no capability "{} {}""#, result.resource, result.ability));
+        }
+        requirements.extend(results);
+    }
+    if let Some(failed) = failed {
+        return Err(anyhow!(failed));
     }
-    let c = Capabilities::try_from(
+    Capabilities::try_from(
         capabilities
             .iter()
-            .map(|c| Capability::from(c.capability.clone()))
+            .map(|c| Capability::from(&c.capability))
             .collect::<Vec<Capability>>(),
-    )?;
+    )
+}
 
-    let mut cids = Vec::new();
-    merge_cids(&chain, &mut cids)?;
+/// Checks the required capability against the capabilities of the chain.
+fn check_requirement<Semantics, S, A>(
+    semantics: &Semantics,
+    capabilities: &[CapabilityInfo<S, A>],
+    cap: Capability,
+    config: &VerifyOptions,
+) -> RequirementResult
+where
+    Semantics: CapabilitySemantics<S, A>,
+    S: Scope,
+    A: Ability,
+{
+    let mut result = RequirementResult {
+        resource: cap.resource.clone(),
+        ability: cap.ability.clone(),
+        ok: false,
+        nearest: None,
+    };
+    if let Some(view) = semantics.parse_capability(&cap) {
+        let root_issuer = normalize_did(&config.root_issuer);
+        // IMPORTANT! check the originator!
+        if capabilities
+            .iter()
+            .any(|c| c.capability.enables(&view) && c.originators.contains(root_issuer))
+        {
+            result.ok = true;
+            return result;
+        }
 
-    Ok(VerifyResponse {
-        capabilities: c,
-        facts: if !facts.is_empty() { Some(facts) } else { None },
-        cids,
-    })
+        // the nearest capability of the resource, labelled by what it lacks
+        let mut nearest_score = 0;
+        for c in capabilities.iter() {
+            if !c.capability.resource().contains(view.resource()) {
+                continue;
+            }
+            let checks = [
+                ("ability", c.capability.ability().enables(view.ability())),
+                (
+                    "caveat",
+                    match (
+                        Caveat::try_from(c.capability.caveat()),
+                        Caveat::try_from(view.caveat()),
+                    ) {
+                        (Ok(c_caveat), Ok(caveat)) => c_caveat.enables(&caveat),
+                        _ => false,
+                    },
+                ),
+                ("originator", c.originators.contains(root_issuer)),
+            ];
+            let score = checks.iter().filter(|check| check.1).count();
+            if result.nearest.is_none() || score > nearest_score {
+                let capability = Capability::from(&c.capability);
+                nearest_score = score;
+                result.nearest = Some(NearestCapability {
+                    resource: capability.resource,
+                    ability: capability.ability,
+                    caveat: capability.caveat,
+                    mismatch: checks
+                        .iter()
+                        .find(|check| !check.1)
+                        .map(|check| check.0.to_owned())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+    }
+    result
 }
 
-fn merge_facts(chain: &ProofChain, facts: &mut FactsMap) {
-    if let Some(f) = chain.ucan().facts() {
-        for item in f.iter() {
-            if !facts.contains_key(item.0) {
-                facts.insert(item.0.clone(), item.1.clone());
+/// Merges facts of the chain, leaf first. With an issuer given, only facts
+/// asserted by this issuer are merged. The digests of hidden facts aren't
+/// merged, as they only apply to the link asserting them.
+pub fn merge_facts(chain: &ProofChain, facts: &mut FactsMap, issuer: Option<&str>) {
+    let mut links = Vec::new();
+    chain_links(chain, issuer, &mut links);
+    for link in links {
+        for (name, value) in link.facts().iter().flatten() {
+            if name != DIGESTS_FACT && !facts.contains_key(name) {
+                facts.insert(name.clone(), value.clone());
             }
         }
     }
+}
+
+/// Adds the links of the chain, leaf first. With an issuer given, only links
+/// issued by this issuer are added.
+fn chain_links<'a>(chain: &'a ProofChain, issuer: Option<&str>, links: &mut Vec<&'a Ucan>) {
+    let issued = match issuer {
+        Some(issuer) => normalize_did(chain.ucan().issuer()) == issuer,
+        None => true,
+    };
+    if issued {
+        links.push(chain.ucan());
+    }
     for c in chain.proofs() {
-        merge_facts(c, facts);
+        chain_links(c, issuer, links);
     }
 }
 
-fn merge_cids(chain: &ProofChain, cids: &mut Vec<String>) -> Result<()> {
+pub fn merge_cids(chain: &ProofChain, cids: &mut Vec<String>) -> Result<()> {
     let cid = chain.ucan().to_cid(Code::Blake3_256)?;
     if !cids.contains(&cid.to_string()) {
         cids.push(cid.to_string());
@@ -343,19 +934,1018 @@ fn merge_cids(chain: &ProofChain, cids: &mut Vec<String>) -> Result<()> {
     Ok(())
 }
 
-pub fn decode_ucan(mut cx: FunctionContext) -> JsResult<JsValue> {
+pub fn proof_source_name(source: ProofSource) -> &'static str {
+    match source {
+        ProofSource::Token => "token",
+        ProofSource::Facts => "facts",
+        ProofSource::Store => "store",
+    }
+}
+
+fn merge_proof_sources(chain: &ProofChain, sources: &mut BTreeMap<String, String>) -> Result<()> {
+    let cid = chain.ucan().to_cid(Code::Blake3_256)?;
+    sources
+        .entry(cid.to_string())
+        .or_insert_with(|| proof_source_name(chain.source()).to_owned());
+    for c in chain.proofs() {
+        merge_proof_sources(c, sources)?;
+    }
+    Ok(())
+}
+
+pub fn verify_ucan_signature(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let token: Handle<neon::types::JsValue> = cx.argument(0)?;
     let token: Handle<JsString> = token.downcast_or_throw(&mut cx)?;
     let token: String = token.value(&mut cx);
-    let ucan = Ucan::try_from(token).or_else(|e| cx.throw_error(e.to_string()))?;
-    let result =
-        neon_serde2::to_value(&mut cx, &ucan).or_else(|e| cx.throw_error(e.to_string()))?;
-    let obj: Handle<JsObject> = result.downcast_or_throw(&mut cx)?;
-    let cid = ucan
-        .to_cid(Code::Blake3_256)
-        .or_else(|e| cx.throw_error(e.to_string()))?;
-    let cid = cx.string(cid.to_string());
-    obj.set(&mut cx, "cid", cid)?;
-
-    Ok(result)
+    let key: Handle<neon::types::JsValue> = cx.argument(1)?;
+    let key: serde_json::Value =
+        neon_serde2::from_value(&mut cx, key).or_else(|e| cx.throw_error(e.to_string()))?;
+    let key = key_from_did_or_jwk(&key).or_else(|e| cx.throw_error(e.to_string()))?;
+
+    // Construct a result promise which will be fulfilled when the computation completes.
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+    let limit = verification_limit(&mut cx)?;
+
+    // Perform the computation in a background thread using the Tokio thread pool.
+    runtime.spawn(async move {
+        let result = limit
+            .run(internal_verify_ucan_signature(&token, key.as_ref()))
+            .await;
+
+        // Resolve the result promise with the result of the computation.
+        deferred.settle_with(&channel, |mut cx| match result {
+            Ok(()) => Ok(cx.boolean(true)),
+            Err(e) => cx.throw_error(e.to_string()),
+        });
+    });
+
+    Ok(promise)
+}
+
+/// Checks only the signature of the token against the given key, whatever
+/// the issuer of the token is.
+pub async fn internal_verify_ucan_signature(token: &str, key: &dyn KeyMaterial) -> Result<()> {
+    let ucan = Ucan::try_from(token)?;
+    if ucan.algorithm() != key.get_jwt_algorithm_name() {
+        return Err(anyhow!(
+            r#"algorithm "{}" doesn't match the key"#,
+            ucan.algorithm()
+        ));
+    }
+    key.verify(ucan.signed_data(), ucan.signature()).await
+}
+
+/// Reads the public key of a did:key DID, or of a JWK (Ed25519 or P-256).
+pub fn key_from_did_or_jwk(key: &serde_json::Value) -> Result<Box<dyn KeyMaterial>> {
+    let decode = |name: &str| -> Result<Vec<u8>> {
+        let value = key[name]
+            .as_str()
+            .ok_or_else(|| anyhow!(r#"invalid JWK: no "{}""#, name))?;
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(value)?)
+    };
+    if let Some(did) = key.as_str() {
+        return Ok(Box::new(DidParser::new(SUPPORTED_KEYS).parse(did)?));
+    }
+    match (key["kty"].as_str(), key["crv"].as_str()) {
+        (Some("OKP"), Some("Ed25519")) => bytes_to_ed25519_key(decode("x")?),
+        (Some("EC"), Some("P-256")) => {
+            // uncompressed SEC1 point
+            bytes_to_p256_key([vec![0x04], decode("x")?, decode("y")?].concat())
+        }
+        (kty, crv) => Err(anyhow!(
+            r#"unsupported JWK: kty "{}", crv "{}""#,
+            kty.unwrap_or_default(),
+            crv.unwrap_or_default()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_functions::reduce_proof_chain;
+    use crate::lint_functions::is_valid_did;
+    use crate::namespace_functions::{create_namespace, drop_namespace, instance_namespace};
+    use crate::test_helpers::{expiration, invoke, invoke_options, new_identity};
+    use base64::Engine;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_delegate_all_from() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let (_, bob_key) = new_identity();
+
+        let capabilities = json!({ "api:app/1": { "book/view": [{}] } });
+        let alice_token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+
+        let mut config = invoke_options(&alice_key, &server_did, &json!({}));
+        config.delegate_all_from = Some(vec![alice_token.clone()]);
+        config.proofs = Some(vec![alice_token.clone()]);
+        let token = invoke(config).await.unwrap();
+
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        let cid = Ucan::try_from(alice_token.as_str())
+            .unwrap()
+            .to_cid(Code::Blake3_256)
+            .unwrap();
+        assert_eq!(ucan.proofs(), &Some(vec![cid.to_string()]));
+        assert!(ucan
+            .capabilities()
+            .get(&format!("ucan:{cid}"))
+            .and_then(|abilities| abilities.get("ucan/*"))
+            .is_some());
+
+        let config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": capabilities,
+        }))
+        .unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let response = internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(response.issuer, alice_did);
+        assert_eq!(response.audience, server_did);
+        assert_eq!(&response.nonce, ucan.nonce());
+        assert_eq!(response.version, ucan.version());
+
+        // the delegated rights belong to alice only
+        let mut config = invoke_options(&bob_key, &server_did, &json!({}));
+        config.delegate_all_from = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+        let config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": capabilities,
+        }))
+        .unwrap();
+        assert!(internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proof_hasher() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let capabilities = json!({ "api:app/1": { "book/view": [{}] } });
+        let alice_token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+        let alice_ucan = Ucan::try_from(alice_token.as_str()).unwrap();
+
+        let mut config = invoke_options(&alice_key, &server_did, &json!({}));
+        config.delegate_all_from = Some(vec![alice_token.clone()]);
+        config.proof_hasher = Some("sha2-256".to_owned());
+        config.typ = Some("ucan+jwt".to_owned());
+        config.header = serde_json::from_value(json!({ "kid": "key-1" })).unwrap();
+        let token = invoke(config).await.unwrap();
+
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        let cid = alice_ucan.to_cid(Code::Sha2_256).unwrap();
+        assert_eq!(ucan.proofs(), &Some(vec![cid.to_string()]));
+        assert!(ucan.capabilities().get(&format!("ucan:{cid}")).is_some());
+        let header = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(token.split('.').next().unwrap())
+            .unwrap();
+        let header: serde_json::Value = serde_json::from_slice(&header).unwrap();
+        assert_eq!(header["typ"], "ucan+jwt");
+        assert_eq!(header["kid"], "key-1");
+
+        let config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": capabilities,
+        }))
+        .unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .unwrap();
+
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.witnessed_by = Some(vec![WitnessOptions {
+            token: alice_token,
+            hasher: Some("blake2b-256".to_owned()),
+        }]);
+        let token = invoke(config).await.unwrap();
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        let cid = alice_ucan.to_cid(Code::Blake2b256).unwrap();
+        assert_eq!(ucan.proofs(), &Some(vec![cid.to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_superuser_resource() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let mut config = invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "*:*": { "user/post": [{}] } }),
+        );
+        config.strict = Some(true);
+        config.superuser_resource = Some("*:*".to_owned());
+        assert!(lint_invoke_options(&config).is_empty());
+        let alice_token = invoke(config).await.unwrap();
+
+        let capabilities = json!({ "api:user/1": { "user/post": [{}] } });
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let mut config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": capabilities,
+        }))
+        .unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        // disabled by default
+        assert!(internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .is_err());
+
+        config = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": capabilities,
+            "superuserResource": "*:*",
+        }))
+        .unwrap();
+        internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ownership_semantics() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let (bob_did, bob_key) = new_identity();
+
+        let mut config = invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "my:api": { "user/post": [{}] } }),
+        );
+        config.semantics = Some("ownership".to_owned());
+        config.strict = Some(true);
+        assert!(lint_invoke_options(&config).is_empty());
+        let alice_token = invoke(config).await.unwrap();
+
+        let mut config = invoke_options(
+            &alice_key,
+            &bob_did,
+            &json!({ format!("as:{server_did}:api"): { "user/post": [{}] } }),
+        );
+        config.proofs = Some(vec![alice_token]);
+        let bob_token = invoke(config).await.unwrap();
+
+        let capabilities = json!({ "api:user/1": { "user/post": [{}] } });
+        let mut config = invoke_options(&bob_key, &server_did, &capabilities);
+        config.proofs = Some(vec![bob_token]);
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |semantics: &str| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "semantics": semantics,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        internal_verify_ucan(&token, verify_options("ownership"), &mut did_parser)
+            .await
+            .unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options("general"), &mut did_parser)
+                .await
+                .is_err()
+        );
+        assert!(
+            internal_verify_ucan(&token, verify_options("unknown"), &mut did_parser)
+                .await
+                .is_err()
+        );
+
+        // "my:" of alice is not owned by the server
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "my:api": { "user/post": [{}] } }),
+        );
+        config.semantics = Some("ownership".to_owned());
+        let token = invoke(config).await.unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options("ownership"), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_facts_scope() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let mut config = invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "api:user": { "user/post": [{}] } }),
+        );
+        config.facts = serde_json::from_value(json!({ "user_id": "1" })).unwrap();
+        let alice_token = invoke(config).await.unwrap();
+
+        // alice asserts another user
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "api:user": { "user/post": [{}] } }),
+        );
+        config.facts = serde_json::from_value(json!({ "user_id": "2" })).unwrap();
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |facts_scope: &str, user_id: &str| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": { "api:user/{user_id}": { "user/post": [{}] } },
+                "requiredFacts": { "user_id": user_id },
+                "factsScope": facts_scope,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        for (facts_scope, user_id) in [("merged", "2"), ("leaf", "2"), ("root", "1")] {
+            let response = internal_verify_ucan(
+                &token,
+                verify_options(facts_scope, user_id),
+                &mut did_parser,
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.facts.unwrap()["user_id"], json!(user_id));
+        }
+        assert!(
+            internal_verify_ucan(&token, verify_options("root", "2"), &mut did_parser)
+                .await
+                .is_err()
+        );
+        assert!(
+            internal_verify_ucan(&token, verify_options("unknown", "2"), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_requirements() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let config = invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "api:user": { "user/view": [{}] } }),
+        );
+        let alice_token = invoke(config).await.unwrap();
+
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "api:user": { "user/view": [{}] }, "api:doc": { "doc/view": [{}] } }),
+        );
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": server_did,
+            "audience": server_did,
+            "requiredCapabilities": {
+                "api:user/1": { "user/view": [{}], "user/edit": [{}] },
+                "api:doc": { "doc/view": [{}] },
+                "api:app": { "app/view": [{}] },
+            },
+        }))
+        .unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let response = internal_check_ucan(&token, config, &mut did_parser).await;
+        assert!(!response.ok);
+        assert!(response.error.is_some());
+        assert!(response.verification.is_none());
+
+        let mismatches: Vec<(&str, &str, bool, Option<&str>)> = response
+            .requirements
+            .iter()
+            .map(|r| {
+                (
+                    r.resource.as_str(),
+                    r.ability.as_str(),
+                    r.ok,
+                    r.nearest.as_ref().map(|n| n.mismatch.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            mismatches,
+            vec![
+                ("api:app", "app/view", false, None),
+                ("api:doc", "doc/view", false, Some("originator")),
+                ("api:user/1", "user/edit", false, Some("ability")),
+                ("api:user/1", "user/view", true, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_proof_chain() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let config = invoke_options(
+            &server_key,
+            &alice_did,
+            &json!({ "api:user": { "user/view": [{}], "user/edit": [{}] } }),
+        );
+        let alice_token = invoke(config).await.unwrap();
+
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "api:user": { "user/view": [{}] } }),
+        );
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        // expired at that time
+        assert!(resolve_proof_chain(
+            &token,
+            ResolveOptions::default(),
+            &None,
+            Some(expiration() + 1),
+            None,
+            &mut did_parser
+        )
+        .await
+        .is_err());
+        let chain = resolve_proof_chain(
+            &token,
+            Default::default(),
+            &None,
+            None,
+            None,
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+
+        let verify_options = |capabilities: serde_json::Value| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+            }))
+            .unwrap()
+        };
+        let response = verify_proof_chain(
+            &chain,
+            &verify_options(json!({ "api:user/1": { "user/view": [{}] } })),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(response.cids.len(), 2);
+        assert!(verify_proof_chain(
+            &chain,
+            &verify_options(json!({ "api:user/1": { "user/edit": [{}] } })),
+            &mut Vec::new(),
+        )
+        .is_err());
+
+        // the time bounds are checked again when reducing
+        let mut config = verify_options(json!({ "api:user/1": { "user/view": [{}] } }));
+        reduce_proof_chain(&chain, &config).unwrap();
+        config.at_time = Some(expiration() + 1);
+        assert!(reduce_proof_chain(&chain, &config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_did_url_audience() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+        let fragment = |did: &str| format!("{}#{}", did, &did["did:key:".len()..]);
+
+        let config = invoke_options(
+            &server_key,
+            &fragment(&alice_did),
+            &json!({ "api:user": { "user/view": [{}] } }),
+        );
+        let alice_token = invoke(config).await.unwrap();
+        assert_eq!(
+            Ucan::try_from(alice_token.as_str()).unwrap().audience(),
+            alice_did
+        );
+
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "api:user": { "user/view": [{}] } }),
+        );
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let config: VerifyOptions = serde_json::from_value(json!({
+            "rootIssuer": fragment(&server_did),
+            "audience": fragment(&server_did),
+            "requiredCapabilities": { "api:user": { "user/view": [{}] } },
+        }))
+        .unwrap();
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        internal_verify_ucan(&token, config, &mut did_parser)
+            .await
+            .unwrap();
+        assert!(is_valid_did(&fragment(&server_did)));
+    }
+
+    #[tokio::test]
+    async fn test_store_proofs() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let config = invoke_options(&server_key, &alice_did, &capabilities);
+        let alice_token = invoke(config).await.unwrap();
+
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.proofs = Some(vec![alice_token.clone()]);
+        let token = invoke(config).await.unwrap();
+
+        // a sibling token without the embedded proof
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.proofs = Some(vec![alice_token]);
+        config.add_proof_facts = Some(false);
+        let sibling_token = invoke(config).await.unwrap();
+
+        // the store of an addon instance
+        let store = MemoryStore::default();
+        let verify_options = |store_proofs: bool| -> VerifyOptions {
+            let mut options: VerifyOptions = serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "storeProofs": store_proofs,
+            }))
+            .unwrap();
+            if store_proofs {
+                options.proof_store = Some(store.clone());
+            }
+            options
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        assert!(
+            internal_verify_ucan(&sibling_token, verify_options(true), &mut did_parser)
+                .await
+                .is_err()
+        );
+        let response = internal_verify_ucan(&token, verify_options(true), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.proof_sources.values().collect::<Vec<&String>>(),
+            vec!["facts"]
+        );
+        assert!(
+            internal_verify_ucan(&sibling_token, verify_options(false), &mut did_parser)
+                .await
+                .is_err()
+        );
+        let response = internal_verify_ucan(&sibling_token, verify_options(true), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.proof_sources.values().collect::<Vec<&String>>(),
+            vec!["store"]
+        );
+
+        // known tokens are only stored once used as proofs
+        let unrelated_token = invoke(invoke_options(&alice_key, &server_did, &json!({})))
+            .await
+            .unwrap();
+        let mut config = verify_options(true);
+        config.known_tokens = Some(vec![unrelated_token.clone()]);
+        internal_verify_ucan(&sibling_token, config, &mut did_parser)
+            .await
+            .unwrap();
+        assert!(store
+            .read_token(
+                &Ucan::try_from(unrelated_token.as_str())
+                    .unwrap()
+                    .to_cid(Code::Blake3_256)
+                    .unwrap()
+            )
+            .await
+            .is_err());
+
+        // another instance doesn't share the stored proofs
+        let mut config = verify_options(true);
+        config.proof_store = Some(MemoryStore::default());
+        assert!(
+            internal_verify_ucan(&sibling_token, config, &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_ucan_signature() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, _) = new_identity();
+
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+        let token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+
+        let key = key_from_did_or_jwk(&json!(server_did)).unwrap();
+        internal_verify_ucan_signature(&token, key.as_ref())
+            .await
+            .unwrap();
+        let key = key_from_did_or_jwk(&json!(alice_did)).unwrap();
+        assert!(internal_verify_ucan_signature(&token, key.as_ref())
+            .await
+            .is_err());
+
+        // the same key as a JWK
+        let (public_key, _) = get_keys(&server_key.public_key, &server_key.private_key).unwrap();
+        let jwk = json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(public_key),
+        });
+        let key = key_from_did_or_jwk(&jwk).unwrap();
+        internal_verify_ucan_signature(&token, key.as_ref())
+            .await
+            .unwrap();
+        assert!(key_from_did_or_jwk(&json!({ "kty": "OKP", "crv": "X25519", "x": "" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strict_audience_chain() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        let capabilities = json!({ "api:app/1": { "book/view": [{}] } });
+        let alice_token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+
+        let verify_options = |strict: bool| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "strictAudienceChain": strict,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+
+        // an invocation
+        let mut config = invoke_options(&alice_key, &server_did, &capabilities);
+        config.proofs = Some(vec![alice_token.clone()]);
+        let token = invoke(config).await.unwrap();
+        internal_verify_ucan(&token, verify_options(true), &mut did_parser)
+            .await
+            .unwrap();
+
+        // a pass-along of the proofs
+        let mut config = invoke_options(&alice_key, &server_did, &json!({}));
+        config.delegate_all_from = Some(vec![alice_token.clone()]);
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+        internal_verify_ucan(&token, verify_options(false), &mut did_parser)
+            .await
+            .unwrap();
+        assert!(
+            internal_verify_ucan(&token, verify_options(true), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parse_failure() {
+        let (server_did, server_key) = new_identity();
+
+        // the unparseable capability comes first
+        let config = invoke_options(
+            &server_key,
+            &server_did,
+            &json!({ "a bad": { "x/y": [{}] }, "api:user": { "user/view": [{}] } }),
+        );
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |parse_failure: &str| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": { "api:user": { "user/view": [{}] } },
+                "parseFailure": parse_failure,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        assert!(
+            internal_verify_ucan(&token, verify_options("truncate"), &mut did_parser)
+                .await
+                .is_err()
+        );
+        let response = internal_verify_ucan(&token, verify_options("skip"), &mut did_parser)
+            .await
+            .unwrap();
+        assert_eq!(response.warnings.len(), 1);
+        assert!(
+            internal_verify_ucan(&token, verify_options("fail"), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verifier_context() {
+        let (server_did, server_key) = new_identity();
+
+        let mut config = invoke_options(
+            &server_key,
+            &server_did,
+            &json!({ "api:tenant/acme": { "tenant/read": [{}] } }),
+        );
+        // the token tries to bind the template
+        config.facts =
+            serde_json::from_value(json!({ "ctx": { "tenant": "acme" }, "tenant": "acme" }))
+                .unwrap();
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |context: serde_json::Value| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": { "api:tenant/{ctx.tenant}": { "tenant/read": [{}] } },
+                "context": context,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        internal_verify_ucan(
+            &token,
+            verify_options(json!({ "tenant": "acme" })),
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+        assert!(internal_verify_ucan(
+            &token,
+            verify_options(json!({ "tenant": "other" })),
+            &mut did_parser
+        )
+        .await
+        .is_err());
+        assert!(
+            internal_verify_ucan(&token, verify_options(json!({})), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_at_time() {
+        let (server_did, server_key) = new_identity();
+        let capabilities = json!({ "api:user": { "user/view": [{}] } });
+
+        let now = ucan::time::now();
+        let mut config = invoke_options(&server_key, &server_did, &capabilities);
+        config.not_before = Some(now + 30);
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |at_time: Option<u64>| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "atTime": at_time,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        // not active yet
+        assert!(
+            internal_verify_ucan(&token, verify_options(None), &mut did_parser)
+                .await
+                .is_err()
+        );
+        internal_verify_ucan(&token, verify_options(Some(now + 45)), &mut did_parser)
+            .await
+            .unwrap();
+        // expired
+        assert!(internal_verify_ucan(
+            &token,
+            verify_options(Some(expiration() + 1)),
+            &mut did_parser
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_semantics_registry() {
+        let (server_did, server_key) = new_identity();
+        let capabilities = json!({ "ucan:*": { "ucan/*": [{}] } });
+
+        let mut config = invoke_options(&server_key, &server_did, &capabilities);
+        config.semantics = Some("proof".to_owned());
+        config.strict = Some(true);
+        assert!(lint_invoke_options(&config).is_empty());
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |semantics: &str, capabilities: &serde_json::Value| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": capabilities,
+                "semantics": semantics,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        internal_verify_ucan(
+            &token,
+            verify_options("proof", &capabilities),
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+        // only delegation of proofs
+        assert!(internal_verify_ucan(
+            &token,
+            verify_options("proof", &json!({ "api:user": { "user/view": [{}] } })),
+            &mut did_parser
+        )
+        .await
+        .is_err());
+
+        let error = internal_verify_ucan(
+            &token,
+            verify_options("unknown", &capabilities),
+            &mut did_parser,
+        )
+        .await
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with(r#"unsupported semantics "unknown", expected one of: general"#));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_nonce() {
+        let (alice_did, _) = new_identity();
+        let (_, server_key) = new_identity();
+        let ledger = SharedLedger::default();
+        let issue = |nonce: &str| {
+            let mut config = invoke_options(&server_key, &alice_did, &json!({}));
+            config.add_nonce = Some(true);
+            config.nonce = Some(nonce.to_owned());
+            config.ledger = ledger.clone();
+            invoke(config)
+        };
+
+        let token = issue("fixed").await.unwrap();
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        assert_eq!(ucan.nonce().as_deref(), Some("fixed"));
+        assert_eq!(
+            issue("fixed").await.unwrap_err().to_string(),
+            r#"duplicate nonce "fixed""#
+        );
+        issue("other").await.unwrap();
+
+        // without addNonce, nothing is recorded
+        let mut config = invoke_options(&server_key, &alice_did, &json!({}));
+        config.nonce = Some("fixed".to_owned());
+        config.ledger = ledger.clone();
+        let token = invoke(config).await.unwrap();
+        assert!(Ucan::try_from(token.as_str()).unwrap().nonce().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_namespace_ledger() {
+        let (alice_did, _) = new_identity();
+        let (_, server_key) = new_identity();
+
+        // the instances of two worker threads
+        let (instance, worker) = (crate::Instance::default(), crate::Instance::default());
+        let (tenant, other) = ("tenant", "other");
+        assert!(create_namespace(&instance, tenant).unwrap());
+        assert!(create_namespace(&instance, other).unwrap());
+        assert!(!create_namespace(&instance, tenant).unwrap());
+        assert!(instance_namespace(&worker, Some(tenant)).is_err());
+        assert!(create_namespace(&worker, tenant).unwrap());
+        let tenant_ledger = instance_namespace(&instance, Some(tenant)).unwrap().ledger;
+        assert!(Arc::ptr_eq(
+            &tenant_ledger,
+            &instance_namespace(&instance, Some(tenant)).unwrap().ledger
+        ));
+
+        let mut config = invoke_options(&server_key, &alice_did, &json!({}));
+        config.add_nonce = Some(true);
+        config.ledger = tenant_ledger.clone();
+        let token = invoke(config).await.unwrap();
+
+        let ucan = Ucan::try_from(token.as_str()).unwrap();
+        let cid = ucan.to_cid(Code::Blake3_256).unwrap().to_string();
+        let nonce = ucan.nonce().as_deref().unwrap();
+        let expiration = ucan.expires_at().unwrap();
+        let now = ucan::time::now();
+        // the nonce is recorded in the ledger of the namespace only
+        assert!(lock_ledger(&tenant_ledger)
+            .unwrap()
+            .record(&cid, nonce, expiration, now)
+            .is_err());
+        for (instance, name) in [
+            (&instance, None),
+            (&instance, Some(other)),
+            (&worker, None),
+            (&worker, Some(tenant)),
+        ] {
+            lock_ledger(&instance_namespace(instance, name).unwrap().ledger)
+                .unwrap()
+                .record(&cid, nonce, expiration, now)
+                .unwrap();
+        }
+
+        // created again, the namespace is empty
+        assert!(drop_namespace(&instance, tenant).unwrap());
+        assert!(!drop_namespace(&instance, tenant).unwrap());
+        assert!(instance_namespace(&instance, Some(tenant)).is_err());
+        assert!(create_namespace(&instance, tenant).unwrap());
+        lock_ledger(&instance_namespace(&instance, Some(tenant)).unwrap().ledger)
+            .unwrap()
+            .record(&cid, nonce, expiration, now)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ability_aliases() {
+        let (server_did, server_key) = new_identity();
+        let (alice_did, alice_key) = new_identity();
+
+        // delegated before "msg/send" was renamed to "mail/send"
+        let capabilities = json!({ "api:mail": { "msg/send": [{}] } });
+        let alice_token = invoke(invoke_options(&server_key, &alice_did, &capabilities))
+            .await
+            .unwrap();
+        let mut config = invoke_options(
+            &alice_key,
+            &server_did,
+            &json!({ "api:mail": { "mail/send": [{}] } }),
+        );
+        config.proofs = Some(vec![alice_token]);
+        let token = invoke(config).await.unwrap();
+
+        let verify_options = |aliases: serde_json::Value| -> VerifyOptions {
+            serde_json::from_value(json!({
+                "rootIssuer": server_did,
+                "audience": server_did,
+                "requiredCapabilities": { "api:mail": { "mail/send": [{}] } },
+                "abilityAliases": aliases,
+            }))
+            .unwrap()
+        };
+        let mut did_parser = DidParser::new(SUPPORTED_KEYS);
+        let response = internal_verify_ucan(
+            &token,
+            verify_options(json!({ "msg/send": "mail/send" })),
+            &mut did_parser,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&response.capabilities).unwrap(),
+            json!({ "api:mail": { "mail/send": [{}] } })
+        );
+        assert!(
+            internal_verify_ucan(&token, verify_options(json!({})), &mut did_parser)
+                .await
+                .is_err()
+        );
+    }
 }